@@ -0,0 +1,426 @@
+use crate::engine::{model, texture};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+// A compiled shader, loaded from res/.
+pub struct Shader {
+    res_path: String,
+    module: wgpu::ShaderModule,
+}
+
+impl Shader {
+    pub fn new(res_path: &str, module: wgpu::ShaderModule) -> Self {
+        Self {
+            res_path: res_path.into(),
+            module,
+        }
+    }
+
+    pub fn res_path(&self) -> &str {
+        &self.res_path
+    }
+
+    pub fn module(&self) -> &wgpu::ShaderModule {
+        &self.module
+    }
+}
+
+// Refers to an asset stored in the AssetServer. Handles to unloaded assets
+// stay invalid even if their slot gets reused.
+pub struct Handle<T> {
+    index: usize,
+    generation: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(index: usize, generation: u32) -> Self {
+        Self {
+            index,
+            generation,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({}, {})", self.index, self.generation)
+    }
+}
+
+struct Slot<T> {
+    generation: u32,
+    entry: Option<Entry<T>>,
+}
+
+struct Entry<T> {
+    asset: T,
+    res_path: Option<String>,
+    ref_count: u32,
+}
+
+// Storage for one type of asset.
+pub struct Assets<T> {
+    slots: Vec<Slot<T>>,
+    free_slots: Vec<usize>,
+    by_path: HashMap<String, usize>,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+            by_path: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Assets<T> {
+    fn insert(&mut self, asset: T, res_path: Option<&str>) -> Handle<T> {
+        let entry = Some(Entry {
+            asset,
+            res_path: res_path.map(|p| p.into()),
+            ref_count: 1,
+        });
+
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                self.slots[index].entry = entry;
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry,
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        if let Some(res_path) = res_path {
+            self.by_path.insert(res_path.into(), index);
+        }
+
+        Handle::new(index, self.slots[index].generation)
+    }
+
+    fn entry(&self, handle: Handle<T>) -> Option<&Entry<T>> {
+        let slot = self.slots.get(handle.index)?;
+
+        if slot.generation != handle.generation {
+            return None;
+        }
+
+        slot.entry.as_ref()
+    }
+
+    fn entry_mut(&mut self, handle: Handle<T>) -> Option<&mut Entry<T>> {
+        let slot = self.slots.get_mut(handle.index)?;
+
+        if slot.generation != handle.generation {
+            return None;
+        }
+
+        slot.entry.as_mut()
+    }
+
+    // Find an already loaded asset and add a reference to it.
+    fn retain_path(&mut self, res_path: &str) -> Option<Handle<T>> {
+        let index = *self.by_path.get(res_path)?;
+        let handle = Handle::new(index, self.slots[index].generation);
+        self.entry_mut(handle)?.ref_count += 1;
+
+        Some(handle)
+    }
+
+    fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        self.entry(handle)?;
+
+        let slot = &mut self.slots[handle.index];
+        let entry = slot.entry.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(handle.index);
+
+        if let Some(res_path) = &entry.res_path {
+            self.by_path.remove(res_path);
+        }
+
+        Some(entry.asset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free_slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Types that can be stored in the AssetServer.
+pub trait Asset: Sized + 'static {
+    fn assets(server: &AssetServer) -> &Assets<Self>;
+    fn assets_mut(server: &mut AssetServer) -> &mut Assets<Self>;
+}
+
+impl Asset for texture::Texture {
+    fn assets(server: &AssetServer) -> &Assets<Self> {
+        &server.textures
+    }
+
+    fn assets_mut(server: &mut AssetServer) -> &mut Assets<Self> {
+        &mut server.textures
+    }
+}
+
+// Only models using the engine's Vertex format can be stored in the asset server.
+impl Asset for model::Model {
+    fn assets(server: &AssetServer) -> &Assets<Self> {
+        &server.models
+    }
+
+    fn assets_mut(server: &mut AssetServer) -> &mut Assets<Self> {
+        &mut server.models
+    }
+}
+
+impl Asset for Shader {
+    fn assets(server: &AssetServer) -> &Assets<Self> {
+        &server.shaders
+    }
+
+    fn assets_mut(server: &mut AssetServer) -> &mut Assets<Self> {
+        &mut server.shaders
+    }
+}
+
+impl Asset for wgpu::RenderPipeline {
+    fn assets(server: &AssetServer) -> &Assets<Self> {
+        &server.pipelines
+    }
+
+    fn assets_mut(server: &mut AssetServer) -> &mut Assets<Self> {
+        &mut server.pipelines
+    }
+}
+
+// Owns assets that are shared between parts of a game. Assets loaded from the same path
+// are only loaded once, and each load adds a reference that should be released when
+// it's no longer needed. GPU resources are freed once an asset is released or unloaded.
+#[derive(Default)]
+pub struct AssetServer {
+    textures: Assets<texture::Texture>,
+    models: Assets<model::Model>,
+    shaders: Assets<Shader>,
+    pipelines: Assets<wgpu::RenderPipeline>,
+}
+
+impl AssetServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Store an asset that wasn't loaded from a file, with a single reference.
+    pub fn add<T: Asset>(&mut self, asset: T) -> Handle<T> {
+        T::assets_mut(self).insert(asset, None)
+    }
+
+    // Return the handle for an asset that was already loaded from the path, adding a
+    // reference to it, or load it with the given function otherwise.
+    pub fn get_or_load<T: Asset, E>(
+        &mut self,
+        res_path: &str,
+        load: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Handle<T>, E> {
+        let assets = T::assets_mut(self);
+
+        if let Some(handle) = assets.retain_path(res_path) {
+            return Ok(handle);
+        }
+
+        Ok(assets.insert(load()?, Some(res_path)))
+    }
+
+    // Return the handle for an asset that was loaded from the path, without adding a reference.
+    pub fn handle_for_path<T: Asset>(&self, res_path: &str) -> Option<Handle<T>> {
+        let assets = T::assets(self);
+        let index = *assets.by_path.get(res_path)?;
+
+        Some(Handle::new(index, assets.slots[index].generation))
+    }
+
+    pub fn get<T: Asset>(&self, handle: Handle<T>) -> Option<&T> {
+        T::assets(self).entry(handle).map(|e| &e.asset)
+    }
+
+    pub fn get_mut<T: Asset>(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        T::assets_mut(self).entry_mut(handle).map(|e| &mut e.asset)
+    }
+
+    pub fn res_path<T: Asset>(&self, handle: Handle<T>) -> Option<&str> {
+        T::assets(self).entry(handle)?.res_path.as_deref()
+    }
+
+    pub fn ref_count<T: Asset>(&self, handle: Handle<T>) -> u32 {
+        T::assets(self).entry(handle).map_or(0, |e| e.ref_count)
+    }
+
+    pub fn retain<T: Asset>(&mut self, handle: Handle<T>) {
+        if let Some(entry) = T::assets_mut(self).entry_mut(handle) {
+            entry.ref_count += 1;
+        }
+    }
+
+    // Remove a reference to the asset, unloading it if that was the last one.
+    // Returns true if the asset was unloaded.
+    pub fn release<T: Asset>(&mut self, handle: Handle<T>) -> bool {
+        let assets = T::assets_mut(self);
+
+        match assets.entry_mut(handle) {
+            Some(entry) if entry.ref_count > 1 => {
+                entry.ref_count -= 1;
+                false
+            }
+            Some(_) => assets.remove(handle).is_some(),
+            None => false,
+        }
+    }
+
+    // Unload the asset right away, no matter how many references it has.
+    pub fn unload<T: Asset>(&mut self, handle: Handle<T>) -> Option<T> {
+        T::assets_mut(self).remove(handle)
+    }
+
+    pub fn assets<T: Asset>(&self) -> &Assets<T> {
+        T::assets(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::texture::tests::create_test_device;
+
+    #[test]
+    fn reused_slots_invalidate_old_handles() {
+        let mut assets = Assets::default();
+        let first = assets.insert("first", None);
+        assert_eq!(assets.remove(first), Some("first"));
+        assert!(assets.is_empty());
+
+        let second = assets.insert("second", None);
+        assert_eq!(second.index, first.index);
+        assert_ne!(second, first);
+        assert!(assets.entry(first).is_none());
+        assert_eq!(assets.entry(second).map(|e| e.asset), Some("second"));
+
+        // Removing through a stale handle leaves the new asset alone.
+        assert_eq!(assets.remove(first), None);
+        assert_eq!(assets.len(), 1);
+    }
+
+    #[test]
+    fn paths_are_forgotten_when_removed() {
+        let mut assets = Assets::default();
+        let handle = assets.insert(1, Some("a.png"));
+
+        assert_eq!(assets.retain_path("a.png"), Some(handle));
+        assert_eq!(assets.entry(handle).map(|e| e.ref_count), Some(2));
+
+        assets.remove(handle);
+        assert_eq!(assets.retain_path("a.png"), None);
+    }
+
+    #[test]
+    fn assets_are_unloaded_after_their_last_release() {
+        let (device, _) = create_test_device();
+        let create_texture = || {
+            texture::Texture::from_dimensions(
+                &device,
+                1,
+                1,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                wgpu::TextureUsages::empty(),
+                None,
+            )
+        };
+
+        let mut server = AssetServer::new();
+        let loads = std::cell::Cell::new(0);
+        let load = |server: &mut AssetServer| {
+            server.get_or_load("a.png", || {
+                loads.set(loads.get() + 1);
+                create_texture()
+            })
+        };
+
+        let handle = load(&mut server).unwrap();
+        assert_eq!(load(&mut server).unwrap(), handle);
+        assert_eq!(loads.get(), 1);
+        assert_eq!(server.ref_count(handle), 2);
+        assert_eq!(server.handle_for_path("a.png"), Some(handle));
+
+        server.retain(handle);
+        assert_eq!(server.ref_count(handle), 3);
+
+        assert!(!server.release(handle));
+        assert!(!server.release(handle));
+        assert!(server.get(handle).is_some());
+        assert!(server.release(handle));
+        assert!(server.get(handle).is_none());
+        assert_eq!(server.ref_count(handle), 0);
+        assert!(!server.release(handle));
+
+        // Loading the path again creates a new asset with a new handle.
+        let reloaded = load(&mut server).unwrap();
+        assert_ne!(reloaded, handle);
+        assert_eq!(loads.get(), 2);
+    }
+
+    #[test]
+    fn unload_ignores_references() {
+        let mut server = AssetServer::new();
+        let (device, _) = create_test_device();
+        let texture = texture::Texture::from_dimensions(
+            &device,
+            1,
+            1,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::empty(),
+            None,
+        )
+        .unwrap();
+
+        let handle = server.add(texture);
+        server.retain(handle);
+
+        assert!(server.unload(handle).is_some());
+        assert!(server.get(handle).is_none());
+        assert!(server.assets::<texture::Texture>().is_empty());
+    }
+}
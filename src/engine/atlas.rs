@@ -0,0 +1,443 @@
+use crate::engine::{error, vfs};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+const ATLAS_HEADER: &str = "# atlas v1";
+
+// A rectangle within the atlas, in pixels, not including padding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// A rectangle within the atlas in texture coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    // Map texture coordinates for a whole image, eg. a quad's [0, 1] corners, into this rect.
+    pub fn map(&self, tex_coords: [f32; 2]) -> [f32; 2] {
+        [
+            self.min[0] + (self.max[0] - self.min[0]) * tex_coords[0],
+            self.min[1] + (self.max[1] - self.min[1]) * tex_coords[1],
+        ]
+    }
+}
+
+// Collects images to be packed into an atlas.
+pub struct AtlasBuilder {
+    // Pixels around each image, filled by extending its edges so that
+    // filtering and rounding errors don't sample neighbouring images.
+    pub padding: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(padding: u32) -> Self {
+        Self {
+            padding,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn add_image(&mut self, name: &str, image: image::RgbaImage) {
+        self.images.push((name.into(), image));
+    }
+
+    // Load an image from the VFS, named after its path.
+    pub fn add_res(&mut self, vfs: &vfs::Vfs, res_path: &str) -> Result<(), error::EngineError> {
+        let image = vfs.read_image(res_path)?;
+        self.add_image(res_path, image.to_rgba8());
+
+        Ok(())
+    }
+
+    // Pack the images into rows, tallest first, doubling the atlas' width until it's roughly square.
+    pub fn build(&self) -> Atlas {
+        let padding = self.padding;
+        let padded_sizes = self
+            .images
+            .iter()
+            .map(|(_, image)| (image.width() + padding * 2, image.height() + padding * 2))
+            .collect::<Vec<_>>();
+
+        let mut order = (0..self.images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| std::cmp::Reverse(padded_sizes[i].1));
+
+        let area = padded_sizes.iter().map(|(w, h)| w * h).sum::<u32>();
+        let widest = padded_sizes.iter().map(|(w, _)| *w).max().unwrap_or(1);
+        let mut width = ((area as f32).sqrt().ceil() as u32)
+            .max(widest)
+            .max(1)
+            .next_power_of_two();
+
+        let (positions, height) = loop {
+            let (positions, height) = pack_rows(&padded_sizes, &order, width);
+
+            if height <= width {
+                break (positions, height.max(1).next_power_of_two());
+            }
+
+            width *= 2;
+        };
+
+        let mut image = image::RgbaImage::new(width, height);
+        let mut rects = HashMap::new();
+
+        for (i, (name, source)) in self.images.iter().enumerate() {
+            let (cell_x, cell_y) = positions[i];
+            let (cell_width, cell_height) = padded_sizes[i];
+            let is_empty = source.width() == 0 || source.height() == 0;
+            let cell_height = if is_empty { 0 } else { cell_height };
+
+            for y in 0..cell_height {
+                for x in 0..cell_width {
+                    let source_x = x.saturating_sub(padding).min(source.width() - 1);
+                    let source_y = y.saturating_sub(padding).min(source.height() - 1);
+                    image.put_pixel(
+                        cell_x + x,
+                        cell_y + y,
+                        *source.get_pixel(source_x, source_y),
+                    );
+                }
+            }
+
+            rects.insert(
+                name.clone(),
+                AtlasRect {
+                    x: cell_x + padding,
+                    y: cell_y + padding,
+                    width: source.width(),
+                    height: source.height(),
+                },
+            );
+        }
+
+        Atlas { image, rects }
+    }
+}
+
+// Returns the position of each size, and the total height needed.
+fn pack_rows(sizes: &[(u32, u32)], order: &[usize], width: u32) -> (Vec<(u32, u32)>, u32) {
+    let mut positions = vec![(0, 0); sizes.len()];
+    let mut x = 0;
+    let mut y = 0;
+    let mut row_height = 0;
+
+    for &i in order {
+        let (size_width, size_height) = sizes[i];
+
+        if x + size_width > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+
+        positions[i] = (x, y);
+        x += size_width;
+        row_height = row_height.max(size_height);
+    }
+
+    (positions, y + row_height)
+}
+
+// Many images packed into one, which can be turned into a single texture.
+// Atlases are saved as a PNG alongside a text file, with one "<x> <y> <width> <height> <name>"
+// line per image, so that release builds can load them from res/ without packing.
+pub struct Atlas {
+    image: image::RgbaImage,
+    rects: HashMap<String, AtlasRect>,
+}
+
+impl Atlas {
+    pub fn image(&self) -> &image::RgbaImage {
+        &self.image
+    }
+
+    pub fn rect(&self, name: &str) -> Option<AtlasRect> {
+        self.rects.get(name).copied()
+    }
+
+    pub fn uv_rect(&self, name: &str) -> Option<UvRect> {
+        let rect = self.rect(name)?;
+        let width = self.image.width() as f32;
+        let height = self.image.height() as f32;
+
+        Some(UvRect {
+            min: [rect.x as f32 / width, rect.y as f32 / height],
+            max: [
+                (rect.x + rect.width) as f32 / width,
+                (rect.y + rect.height) as f32 / height,
+            ],
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.rects.keys().map(|name| name.as_str())
+    }
+
+    pub fn save(&self, image_path: &Path, metadata_path: &Path) -> Result<(), error::EngineError> {
+        self.image.save(image_path)?;
+
+        let mut file = io::BufWriter::new(std::fs::File::create(metadata_path)?);
+        self.write_rects(&mut file)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    fn write_rects(&self, mut file: impl Write) -> Result<(), io::Error> {
+        writeln!(file, "{}", ATLAS_HEADER)?;
+
+        // Sort by name so that saved files are stable between runs.
+        let mut rects = self.rects.iter().collect::<Vec<_>>();
+        rects.sort_by_key(|r| r.0);
+        for (name, rect) in rects {
+            writeln!(
+                file,
+                "{} {} {} {} {}",
+                rect.x, rect.y, rect.width, rect.height, name
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Load an atlas that was saved into res/, so that it can come from any mount.
+    pub fn load(
+        vfs: &vfs::Vfs,
+        image_res_path: &str,
+        metadata_res_path: &str,
+    ) -> Result<Self, error::EngineError> {
+        let image = vfs.read_image(image_res_path)?.to_rgba8();
+        let metadata = vfs
+            .read(metadata_res_path)
+            .map_err(|e| error::EngineError::resource(metadata_res_path, e))?;
+        let rects = Self::read_rects(&image, metadata.as_slice())
+            .map_err(|e| error::EngineError::resource(metadata_res_path, e))?;
+
+        Ok(Self { image, rects })
+    }
+
+    // Read rects that were written by write_rects, checking that they fit in the image.
+    fn read_rects(
+        image: &image::RgbaImage,
+        reader: impl BufRead,
+    ) -> Result<HashMap<String, AtlasRect>, io::Error> {
+        let mut rects = HashMap::new();
+
+        for (line_i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Garbage metadata can overflow, which shouldn't wrap around to a rect that fits.
+            let fits = |start: u32, size: u32, max: u32| {
+                start.checked_add(size).is_some_and(|end| end <= max)
+            };
+            let (name, rect) = Self::parse_line(line)
+                .filter(|(_, rect)| {
+                    fits(rect.x, rect.width, image.width())
+                        && fits(rect.y, rect.height, image.height())
+                })
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid atlas rect on line {}: {}", line_i + 1, line),
+                    )
+                })?;
+
+            if rects.contains_key(&name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Duplicate atlas rect on line {}: {}", line_i + 1, name),
+                ));
+            }
+
+            rects.insert(name, rect);
+        }
+
+        Ok(rects)
+    }
+
+    fn parse_line(line: &str) -> Option<(String, AtlasRect)> {
+        let mut parts = line.splitn(5, ' ');
+
+        let rect = AtlasRect {
+            x: parts.next()?.parse().ok()?,
+            y: parts.next()?.parse().ok()?,
+            width: parts.next()?.parse().ok()?,
+            height: parts.next()?.parse().ok()?,
+        };
+        let name = parts.next()?;
+
+        if name.is_empty() {
+            return None;
+        }
+
+        Some((name.into(), rect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_image(width: u32, height: u32, value: u8) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    fn padded(rect: AtlasRect, padding: u32) -> AtlasRect {
+        AtlasRect {
+            x: rect.x - padding,
+            y: rect.y - padding,
+            width: rect.width + padding * 2,
+            height: rect.height + padding * 2,
+        }
+    }
+
+    fn overlaps(a: AtlasRect, b: AtlasRect) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn packed_images_fit_without_overlapping() {
+        let padding = 1;
+        let mut builder = AtlasBuilder::new(padding);
+        let sizes = [
+            (16, 16),
+            (8, 24),
+            (30, 2),
+            (1, 1),
+            (12, 7),
+            (5, 5),
+            (16, 16),
+        ];
+        for (i, (width, height)) in sizes.iter().enumerate() {
+            builder.add_image(&i.to_string(), solid_image(*width, *height, i as u8));
+        }
+
+        let atlas = builder.build();
+        let (atlas_width, atlas_height) = atlas.image().dimensions();
+        assert!(atlas_width.is_power_of_two() && atlas_height.is_power_of_two());
+
+        let rects = (0..sizes.len())
+            .map(|i| atlas.rect(&i.to_string()).unwrap())
+            .collect::<Vec<_>>();
+
+        for (i, rect) in rects.iter().enumerate() {
+            assert_eq!((rect.width, rect.height), sizes[i]);
+
+            let padded_rect = padded(*rect, padding);
+            assert!(padded_rect.x + padded_rect.width <= atlas_width);
+            assert!(padded_rect.y + padded_rect.height <= atlas_height);
+
+            for other in &rects[i + 1..] {
+                assert!(!overlaps(padded_rect, padded(*other, padding)));
+            }
+
+            // Each image is copied to its rect unchanged.
+            let pixel = atlas.image().get_pixel(rect.x, rect.y);
+            assert_eq!(pixel.0[0], i as u8);
+        }
+    }
+
+    #[test]
+    fn padding_extends_the_edges() {
+        let mut image = image::RgbaImage::new(2, 2);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            *pixel = image::Rgba([x as u8, y as u8, 7, 255]);
+        }
+
+        let padding = 2;
+        let mut builder = AtlasBuilder::new(padding);
+        builder.add_image("image", image.clone());
+        let atlas = builder.build();
+        let rect = padded(atlas.rect("image").unwrap(), padding);
+
+        // Every padded pixel matches the nearest pixel of the image.
+        for y in 0..rect.height {
+            for x in 0..rect.width {
+                let source_x = x.saturating_sub(padding).min(1);
+                let source_y = y.saturating_sub(padding).min(1);
+                assert_eq!(
+                    atlas.image().get_pixel(rect.x + x, rect.y + y),
+                    image.get_pixel(source_x, source_y),
+                    "{} {}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rects_round_trip() {
+        let mut builder = AtlasBuilder::new(1);
+        builder.add_image("player.png", solid_image(4, 8, 1));
+        builder.add_image("ui/a name with spaces.png", solid_image(3, 3, 2));
+        let atlas = builder.build();
+
+        let mut bytes = Vec::new();
+        atlas.write_rects(&mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.starts_with(ATLAS_HEADER));
+
+        let rects = Atlas::read_rects(atlas.image(), text.as_bytes()).unwrap();
+        assert_eq!(rects, atlas.rects);
+    }
+
+    #[test]
+    fn saved_atlases_load_from_the_vfs() {
+        let mut builder = AtlasBuilder::new(0);
+        builder.add_image("a", solid_image(2, 2, 9));
+        let atlas = builder.build();
+
+        let dir = std::env::temp_dir().join(format!("atlas_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        atlas
+            .save(&dir.join("atlas.png"), &dir.join("atlas.txt"))
+            .unwrap();
+
+        let vfs = vfs::Vfs::from_sources(&[vfs::MountSource::Dir(dir.clone())]).unwrap();
+        let loaded = Atlas::load(&vfs, "atlas.png", "atlas.txt");
+        let missing = Atlas::load(&vfs, "atlas.png", "missing.txt");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.image(), atlas.image());
+        assert_eq!(loaded.rect("a"), atlas.rect("a"));
+        assert!(matches!(
+            missing,
+            Err(error::EngineError::Resource { res_path, .. }) if res_path == "missing.txt"
+        ));
+    }
+
+    #[test]
+    fn invalid_rects_are_rejected() {
+        let image = solid_image(8, 8, 0);
+
+        for text in [
+            "0 0 4\n",
+            "0 0 4 4\n",
+            "0 0 four 4 name\n",
+            "6 0 4 4 name\n",
+            "4294967295 0 2 4 name\n",
+            "0 4294967295 4 2 name\n",
+            "0 0 4 4 name\n4 4 4 4 name\n",
+        ] {
+            let error = Atlas::read_rects(&image, text.as_bytes()).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use wgpu::util::DeviceExt;
+
+// A GPU buffer that's updated in place with queue.write_buffer. It only reallocates when
+// the contents outgrow it, doubling its capacity so that growing contents rarely need to.
+pub struct DynamicBuffer<T: bytemuck::Pod> {
+    buffer: wgpu::Buffer,
+    usage: wgpu::BufferUsages,
+    label: Option<String>,
+    capacity: usize,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> DynamicBuffer<T> {
+    pub fn new(
+        device: &wgpu::Device,
+        contents: &[T],
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> Self {
+        if contents.is_empty() {
+            return Self::with_capacity(device, 0, usage, label);
+        }
+
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+
+        Self {
+            buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label,
+                contents: bytemuck::cast_slice(contents),
+                usage,
+            }),
+            usage,
+            label: label.map(|l| l.into()),
+            capacity: contents.len(),
+            len: contents.len(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn with_capacity(
+        device: &wgpu::Device,
+        capacity: usize,
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> Self {
+        let capacity = capacity.max(1);
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+
+        Self {
+            buffer: Self::create_buffer(device, capacity, usage, label),
+            usage,
+            label: label.map(|l| l.into()),
+            capacity,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        capacity: usize,
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size: align_to_copy(capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Replace the buffer's contents. Returns true if the buffer had to be reallocated,
+    // in which case bind groups using it need to be recreated.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, contents: &[T]) -> bool {
+        let new_capacity = grown_capacity(self.capacity, contents.len());
+
+        if let Some(capacity) = new_capacity {
+            self.capacity = capacity;
+            self.buffer =
+                Self::create_buffer(device, self.capacity, self.usage, self.label.as_deref());
+        }
+
+        self.len = contents.len();
+
+        let bytes: &[u8] = bytemuck::cast_slice(contents);
+        if !bytes.is_empty() {
+            // Writes have to be a multiple of 4 bytes long, eg. an odd number of u16 indices.
+            let bytes = if bytes.len() == align_to_copy(bytes.len()) {
+                Cow::Borrowed(bytes)
+            } else {
+                let mut padded = bytes.to_vec();
+                padded.resize(align_to_copy(bytes.len()), 0);
+                Cow::Owned(padded)
+            };
+
+            queue.write_buffer(&self.buffer, 0, &bytes);
+        }
+
+        new_capacity.is_some()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    // The part of the buffer holding the current contents.
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        // Empty slices can't be created, but nothing will be read from an empty buffer anyway.
+        if self.len == 0 {
+            return self.buffer.slice(..);
+        }
+
+        let size = self.len * std::mem::size_of::<T>();
+        self.buffer.slice(..size as wgpu::BufferAddress)
+    }
+
+    // The number of elements in the current contents.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The number of elements that fit before the buffer needs to be reallocated.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+// The capacity a buffer needs to grow to to hold len elements, or None if it's big enough.
+fn grown_capacity(capacity: usize, len: usize) -> Option<usize> {
+    if len > capacity {
+        Some(len.max(capacity * 2))
+    } else {
+        None
+    }
+}
+
+fn align_to_copy(size: usize) -> usize {
+    let alignment = wgpu::COPY_BUFFER_ALIGNMENT as usize;
+    size.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::texture;
+
+    #[test]
+    fn buffers_only_grow_when_outgrown() {
+        assert_eq!(grown_capacity(4, 0), None);
+        assert_eq!(grown_capacity(4, 4), None);
+        assert_eq!(grown_capacity(4, 5), Some(8));
+        assert_eq!(grown_capacity(4, 20), Some(20));
+        assert_eq!(grown_capacity(1, 2), Some(2));
+    }
+
+    #[test]
+    fn sizes_are_aligned_for_copies() {
+        assert_eq!(align_to_copy(0), 0);
+        assert_eq!(align_to_copy(1), 4);
+        assert_eq!(align_to_copy(4), 4);
+        assert_eq!(align_to_copy(6), 8);
+    }
+
+    #[test]
+    fn updates_reallocate_when_contents_outgrow_the_buffer() {
+        let (device, queue) = texture::tests::create_test_device();
+        let mut buffer =
+            DynamicBuffer::<u16>::with_capacity(&device, 0, wgpu::BufferUsages::INDEX, None);
+        assert_eq!(buffer.capacity(), 1);
+
+        assert!(buffer.update(&device, &queue, &[0, 1, 2]));
+        assert_eq!((buffer.len(), buffer.capacity()), (3, 3));
+
+        assert!(!buffer.update(&device, &queue, &[0]));
+        assert_eq!((buffer.len(), buffer.capacity()), (1, 3));
+
+        assert!(buffer.update(&device, &queue, &[0; 4]));
+        assert_eq!((buffer.len(), buffer.capacity()), (4, 6));
+
+        assert!(!buffer.update(&device, &queue, &[]));
+        assert!(buffer.is_empty());
+    }
+}
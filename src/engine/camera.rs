@@ -27,6 +27,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         pos: cgmath::Vector3<f32>,
@@ -72,7 +73,7 @@ impl Camera {
         });
 
         let depth_texture = texture::Texture::create_depth_texture(
-            &device,
+            device,
             screen_width,
             screen_height,
             "depth_texture",
@@ -101,7 +102,7 @@ impl Camera {
         let (new_width, new_height) = self.get_new_size(screen_width, screen_height);
 
         self.depth_texture =
-            texture::Texture::create_depth_texture(&device, new_width, new_height, "depth_texture");
+            texture::Texture::create_depth_texture(device, new_width, new_height, "depth_texture");
     }
 
     fn get_new_size(&self, screen_width: u32, screen_height: u32) -> (u32, u32) {
@@ -0,0 +1,137 @@
+// Decides how many fixed updates to run each frame, based on how much time has passed.
+pub struct FixedClock {
+    fixed_delta: f32,
+    accumulator: f32,
+    max_steps_per_frame: u32,
+    time_scale: f32,
+    paused: bool,
+    pending_steps: u32,
+}
+
+impl FixedClock {
+    pub fn new(fixed_update_rate: u32, max_steps_per_frame: u32) -> Self {
+        Self {
+            fixed_delta: 1.0 / fixed_update_rate as f32,
+            accumulator: 0.0,
+            max_steps_per_frame: max_steps_per_frame.max(1),
+            time_scale: 1.0,
+            paused: false,
+            pending_steps: 0,
+        }
+    }
+
+    // Add a frame's worth of time, returning the number of fixed updates that should run.
+    pub fn advance(&mut self, delta_time: f32) -> u32 {
+        if self.paused {
+            let steps = self.pending_steps;
+            self.pending_steps = 0;
+            return steps;
+        }
+
+        self.accumulator += self.scale_delta(delta_time);
+
+        let mut steps = 0;
+        while self.accumulator >= self.fixed_delta && steps < self.max_steps_per_frame {
+            self.accumulator -= self.fixed_delta;
+            steps += 1;
+        }
+
+        // Drop time that couldn't be caught up on, rather than falling further behind each frame.
+        if steps == self.max_steps_per_frame {
+            self.accumulator = self.accumulator.min(self.fixed_delta);
+        }
+
+        steps
+    }
+
+    // Scale a frame's delta time for the game's variable rate update.
+    pub fn scale_delta(&self, delta_time: f32) -> f32 {
+        if self.paused {
+            0.0
+        } else {
+            delta_time * self.time_scale
+        }
+    }
+
+    // How far between the last fixed update and the next one the current frame is, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_delta).clamp(0.0, 1.0)
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pending_steps = 0;
+    }
+
+    // Run a single fixed update on the next frame, only while paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A rate of 4 keeps every delta exactly representable.
+    const RATE: u32 = 4;
+
+    #[test]
+    fn time_accumulates_between_frames() {
+        let mut clock = FixedClock::new(RATE, 8);
+
+        assert_eq!(clock.advance(0.125), 0);
+        assert_eq!(clock.alpha(), 0.5);
+        assert_eq!(clock.advance(0.125), 1);
+        assert_eq!(clock.alpha(), 0.0);
+        assert_eq!(clock.advance(0.75), 3);
+    }
+
+    #[test]
+    fn long_frames_are_capped() {
+        let mut clock = FixedClock::new(RATE, 2);
+
+        assert_eq!(clock.advance(10.0), 2);
+        // At most one update's worth of time carries over.
+        assert_eq!(clock.alpha(), 1.0);
+        assert_eq!(clock.advance(0.0), 1);
+        assert_eq!(clock.advance(0.0), 0);
+    }
+
+    #[test]
+    fn time_scale_changes_the_update_count() {
+        let mut clock = FixedClock::new(RATE, 8);
+        clock.set_time_scale(2.0);
+
+        assert_eq!(clock.scale_delta(0.25), 0.5);
+        assert_eq!(clock.advance(0.25), 2);
+
+        clock.set_time_scale(-1.0);
+        assert_eq!(clock.advance(1.0), 0);
+    }
+
+    #[test]
+    fn paused_clocks_only_run_steps() {
+        let mut clock = FixedClock::new(RATE, 8);
+        clock.set_paused(true);
+
+        assert_eq!(clock.advance(1.0), 0);
+        assert_eq!(clock.scale_delta(1.0), 0.0);
+
+        clock.step();
+        clock.step();
+        assert_eq!(clock.advance(1.0), 2);
+        assert_eq!(clock.advance(1.0), 0);
+
+        // Steps requested while running are ignored.
+        clock.set_paused(false);
+        clock.step();
+        assert_eq!(clock.advance(0.0), 0);
+    }
+}
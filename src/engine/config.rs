@@ -0,0 +1,69 @@
+use crate::engine::vfs;
+use std::path::PathBuf;
+
+// How the window should fill the screen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    // Covers the monitor without changing its video mode.
+    Borderless,
+    // Switches the monitor to its largest video mode.
+    Exclusive,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+// Window and surface settings that a game chooses before the engine starts.
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+    // Logical window sizes, the OS picks the initial size when this is None.
+    pub window_size: Option<(u32, u32)>,
+    pub min_window_size: Option<(u32, u32)>,
+    pub resizable: bool,
+    pub window_mode: WindowMode,
+    // Where res paths are loaded from, later mounts override files in earlier ones.
+    pub mounts: Vec<vfs::MountSource>,
+    // Res path of a PNG to use as the window's icon.
+    pub icon_res_path: Option<String>,
+    pub present_mode: wgpu::PresentMode,
+    pub power_preference: wgpu::PowerPreference,
+    // The preferred surface format, the first supported format is used if it isn't available.
+    pub color_space: ColorSpace,
+    pub backends: wgpu::Backends,
+    // The most fixed updates that can run in one frame, time beyond that is dropped
+    // so that a long stall doesn't cause a burst of updates.
+    pub max_fixed_updates_per_frame: u32,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            window_size: None,
+            min_window_size: None,
+            resizable: true,
+            window_mode: WindowMode::Windowed,
+            mounts: vec![vfs::MountSource::Dir("res".into())],
+            icon_res_path: None,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            power_preference: wgpu::PowerPreference::default(),
+            color_space: ColorSpace::Srgb,
+            backends: wgpu::Backends::all(),
+            max_fixed_updates_per_frame: 8,
+        }
+    }
+}
+
+// Where games should keep settings files, a "config" directory next to the executable so
+// that settings don't depend on the working directory the game was started from.
+pub fn config_dir() -> PathBuf {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+        .unwrap_or_default();
+
+    exe_dir.join("config")
+}
@@ -1,26 +1,69 @@
-use crate::engine::{camera, instance, model, pipeline, texture, texture_array, vertex};
+use crate::engine::{
+    assets, atlas, buffer, camera, config, error, hot_reload, input, instance, model, pipeline,
+    texture, texture_array, vertex, vfs,
+};
 use wgpu::util::DeviceExt;
 
+// Changes requested by the game, which the engine applies once the current callback returns.
+#[derive(Default)]
+pub struct EngineRequests {
+    pub input_map: Option<input::InputMap>,
+    pub exit: bool,
+    pub window: WindowRequests,
+    pub time_scale: Option<f32>,
+    pub paused: Option<bool>,
+    pub fixed_steps: u32,
+}
+
+// Changes to the window, which are applied by the event loop since games can't access it directly.
+// These have no effect when running headlessly.
+#[derive(Default)]
+pub struct WindowRequests {
+    pub window_mode: Option<config::WindowMode>,
+    pub size: Option<(u32, u32)>,
+    pub cursor_visible: Option<bool>,
+    pub cursor_grab: Option<bool>,
+}
+
 // A simple way to access engine content and state from a game.
 pub struct EngineHandle<'a> {
     device: &'a mut wgpu::Device,
     queue: &'a mut wgpu::Queue,
     config: &'a mut wgpu::SurfaceConfiguration,
     cameras: &'a mut Vec<camera::Camera>,
+    requests: &'a mut EngineRequests,
+    assets: &'a mut assets::AssetServer,
+    reload_registry: &'a mut hot_reload::ReloadRegistry,
+    vfs: &'a mut vfs::Vfs,
+    headless: bool,
+    fullscreen: bool,
 }
 
 impl<'a> EngineHandle<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &'a mut wgpu::Device,
         queue: &'a mut wgpu::Queue,
         config: &'a mut wgpu::SurfaceConfiguration,
         cameras: &'a mut Vec<camera::Camera>,
+        requests: &'a mut EngineRequests,
+        assets: &'a mut assets::AssetServer,
+        reload_registry: &'a mut hot_reload::ReloadRegistry,
+        vfs: &'a mut vfs::Vfs,
+        headless: bool,
+        fullscreen: bool,
     ) -> Self {
         Self {
             device,
             queue,
             config,
             cameras,
+            requests,
+            assets,
+            reload_registry,
+            vfs,
+            headless,
+            fullscreen,
         }
     }
 
@@ -47,7 +90,7 @@ impl<'a> EngineHandle<'a> {
         let fixed_size = set_width.is_some() || set_height.is_some();
 
         let camera = camera::Camera::new(
-            &self.device,
+            self.device,
             pos,
             target,
             up,
@@ -62,10 +105,60 @@ impl<'a> EngineHandle<'a> {
         camera::CameraHandle(new_cam_index)
     }
 
-    pub fn create_model(&mut self, vertices: &[vertex::Vertex], indices: &[u16]) -> model::Model {
+    pub fn create_model<V: vertex::VertexFormat, I: model::Index>(
+        &mut self,
+        vertices: &[V],
+        indices: &[I],
+    ) -> model::Model<V> {
         model::Model::new(self.device, vertices, indices)
     }
 
+    // Like create_model, but uses 16 bit indices when the mesh is small enough for them.
+    pub fn create_compact_model<V: vertex::VertexFormat>(
+        &mut self,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> model::Model<V> {
+        model::Model::new_compact(self.device, vertices, indices)
+    }
+
+    // Replace a model's mesh, reusing its buffers when they're large enough.
+    pub fn update_model<V: vertex::VertexFormat, I: model::Index>(
+        &mut self,
+        model: &mut model::Model<V>,
+        vertices: &[V],
+        indices: &[I],
+    ) {
+        model.update(self.device, self.queue, vertices, indices);
+    }
+
+    pub fn update_compact_model<V: vertex::VertexFormat>(
+        &mut self,
+        model: &mut model::Model<V>,
+        vertices: &[V],
+        indices: &[u32],
+    ) {
+        model.update_compact(self.device, self.queue, vertices, indices);
+    }
+
+    pub fn create_dynamic_buffer<T: bytemuck::Pod>(
+        &mut self,
+        contents: &[T],
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> buffer::DynamicBuffer<T> {
+        buffer::DynamicBuffer::new(self.device, contents, usage, label)
+    }
+
+    // Returns true if the buffer had to be reallocated to fit the contents.
+    pub fn update_dynamic_buffer<T: bytemuck::Pod>(
+        &mut self,
+        buffer: &mut buffer::DynamicBuffer<T>,
+        contents: &[T],
+    ) -> bool {
+        buffer.update(self.device, self.queue, contents)
+    }
+
     pub fn create_texture(
         &mut self,
         width: u32,
@@ -74,27 +167,85 @@ impl<'a> EngineHandle<'a> {
         extra_usages: wgpu::TextureUsages,
         label: Option<&str>,
     ) -> texture::Texture {
+        self.try_create_texture(width, height, format, extra_usages, label)
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Failed to create texture with label {}: {}",
+                    label.unwrap_or("[no label]"),
+                    e
+                )
+            })
+    }
+
+    pub fn try_create_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        extra_usages: wgpu::TextureUsages,
+        label: Option<&str>,
+    ) -> Result<texture::Texture, error::EngineError> {
         texture::Texture::from_dimensions(self.device, width, height, format, extra_usages, label)
-            .expect(&format!(
-                "Failed to create texture with label: {}",
-                match label {
-                    Some(l) => l,
-                    _ => "[no label]",
-                }
-            ))
+    }
+
+    // A render attachment for passes whose pipelines use PipelineBuilder::sample_count.
+    pub fn create_multisampled_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: Option<&str>,
+    ) -> texture::Texture {
+        self.try_create_multisampled_texture(width, height, format, sample_count, label)
+            .unwrap_or_else(|e| panic!("Failed to create multisampled texture: {}", e))
+    }
+
+    pub fn try_create_multisampled_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: Option<&str>,
+    ) -> Result<texture::Texture, error::EngineError> {
+        texture::Texture::create_multisampled(
+            self.device,
+            width,
+            height,
+            format,
+            sample_count,
+            label,
+        )
     }
 
     pub fn create_texture_array(
         &mut self,
         textures: Vec<texture::Texture>,
     ) -> texture_array::TextureArray {
-        texture_array::TextureArray::new(self.device, textures)
-            .expect("Failed to create texture array")
+        self.try_create_texture_array(textures)
+            .unwrap_or_else(|e| panic!("Failed to create texture array: {}", e))
+    }
+
+    pub fn try_create_texture_array(
+        &mut self,
+        textures: Vec<texture::Texture>,
+    ) -> Result<texture_array::TextureArray, error::EngineError> {
+        let res_paths = textures
+            .iter()
+            .map(|t| t.res_path().map(|p| p.to_string()))
+            .collect::<Vec<_>>();
+        let layer_size = textures.first().map_or((0, 0), |t| (t.width(), t.height()));
+        let texture_array = texture_array::TextureArray::new(self.device, self.queue, textures)?;
+        self.reload_registry
+            .watch_texture_array(&texture_array, &res_paths, layer_size);
+
+        Ok(texture_array)
     }
 
     pub fn create_instance_buffer<T: AsRef<instance::Instance>>(
         &mut self,
-        instances: &Vec<T>,
+        instances: &[T],
     ) -> wgpu::Buffer {
         let raw_instances = instances
             .iter()
@@ -111,7 +262,7 @@ impl<'a> EngineHandle<'a> {
 
     pub fn create_instance_buffer_from_raw(
         &mut self,
-        instances: &Vec<instance::InstanceRaw>,
+        instances: &[instance::InstanceRaw],
     ) -> wgpu::Buffer {
         self.device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -121,32 +272,234 @@ impl<'a> EngineHandle<'a> {
             })
     }
 
+    // Create a pipeline stored in the asset server, which gets rebuilt when its shader
+    // changes if hot reloading is enabled. The pipeline is replaced in place, so the
+    // handle stays valid.
     pub fn create_pipeline(
         &mut self,
         shader_res_path: &str,
         bind_group_layouts: &[&wgpu::BindGroupLayout],
         camera_handle: Option<camera::CameraHandle>,
-    ) -> wgpu::RenderPipeline {
+    ) -> assets::Handle<wgpu::RenderPipeline> {
+        self.try_create_pipeline(shader_res_path, bind_group_layouts, camera_handle)
+            .unwrap_or_else(|e| panic!("Failed to create pipeline: {}", e))
+    }
+
+    pub fn try_create_pipeline(
+        &mut self,
+        shader_res_path: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        camera_handle: Option<camera::CameraHandle>,
+    ) -> Result<assets::Handle<wgpu::RenderPipeline>, error::EngineError> {
+        let builder = pipeline::PipelineBuilder::new(shader_res_path);
+        self.try_build_pipeline(&builder, bind_group_layouts, camera_handle)
+    }
+
+    pub fn build_pipeline(
+        &mut self,
+        builder: &pipeline::PipelineBuilder,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        camera_handle: Option<camera::CameraHandle>,
+    ) -> assets::Handle<wgpu::RenderPipeline> {
+        self.try_build_pipeline(builder, bind_group_layouts, camera_handle)
+            .unwrap_or_else(|e| panic!("Failed to build pipeline: {}", e))
+    }
+
+    pub fn try_build_pipeline(
+        &mut self,
+        builder: &pipeline::PipelineBuilder,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        camera_handle: Option<camera::CameraHandle>,
+    ) -> Result<assets::Handle<wgpu::RenderPipeline>, error::EngineError> {
         let mut layouts = bind_group_layouts.to_vec();
 
         if let Some(handle) = camera_handle {
             layouts.push(self.cameras[handle.0].bind_group_layout());
         }
 
-        pipeline::create_pipeline(
+        let pipeline = builder.build(
             self.device,
             self.config.format,
-            shader_res_path,
+            self.vfs,
             layouts.as_slice(),
-        )
+        )?;
+        let handle = self.assets.add(pipeline);
+        self.reload_registry
+            .watch_pipeline(handle, builder.clone(), layouts.len() as u32);
+
+        Ok(handle)
+    }
+
+    // Whether the game is rendering offscreen, eg. in a test, rather than to a window.
+    // Games should avoid writing files like settings when headless.
+    pub fn is_headless(&self) -> bool {
+        self.headless
+    }
+
+    // The size of the window or headless target, in physical pixels.
+    pub fn surface_size(&self) -> (u32, u32) {
+        (self.config.width, self.config.height)
+    }
+
+    // The format that pipelines are created with.
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.config.format
     }
 
     pub fn load_texture(&mut self, res_path: &str) -> texture::Texture {
-        texture::Texture::from_path(self.device, self.queue, res_path)
-            .expect(&format!("Failed to load texture from path: {}", res_path))
+        self.try_load_texture(res_path)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_load_texture(
+        &mut self,
+        res_path: &str,
+    ) -> Result<texture::Texture, error::EngineError> {
+        let texture = texture::Texture::from_path(self.device, self.queue, self.vfs, res_path)?;
+        self.reload_registry.watch_texture(&texture);
+
+        Ok(texture)
+    }
+
+    pub fn vfs(&self) -> &vfs::Vfs {
+        self.vfs
+    }
+
+    // Mount more sources at runtime, eg. to load a mod's files.
+    pub fn vfs_mut(&mut self) -> &mut vfs::Vfs {
+        self.vfs
+    }
+
+    pub fn assets(&self) -> &assets::AssetServer {
+        self.assets
+    }
+
+    pub fn assets_mut(&mut self) -> &mut assets::AssetServer {
+        self.assets
+    }
+
+    // Load a texture through the asset server, reusing it if the path was already loaded.
+    pub fn load_texture_asset(
+        &mut self,
+        res_path: &str,
+    ) -> Result<assets::Handle<texture::Texture>, error::EngineError> {
+        self.assets.get_or_load(res_path, || {
+            let texture = texture::Texture::from_path(self.device, self.queue, self.vfs, res_path)?;
+            self.reload_registry.watch_texture(&texture);
+
+            Ok(texture)
+        })
+    }
+
+    pub fn load_shader_asset(
+        &mut self,
+        res_path: &str,
+    ) -> Result<assets::Handle<assets::Shader>, error::EngineError> {
+        self.assets.get_or_load(res_path, || {
+            pipeline::load_shader(self.device, self.vfs, res_path)
+        })
+    }
+
+    pub fn create_pipeline_with_shader(
+        &mut self,
+        shader: assets::Handle<assets::Shader>,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        camera_handle: Option<camera::CameraHandle>,
+    ) -> Result<wgpu::RenderPipeline, error::EngineError> {
+        let shader = self
+            .assets
+            .get(shader)
+            .ok_or(error::EngineError::InvalidHandle)?;
+        let mut layouts = bind_group_layouts.to_vec();
+
+        if let Some(handle) = camera_handle {
+            layouts.push(self.cameras[handle.0].bind_group_layout());
+        }
+
+        pipeline::create_pipeline_with_shader(
+            self.device,
+            self.config.format,
+            shader,
+            layouts.as_slice(),
+        )
+    }
+
+    pub fn create_atlas_texture(&mut self, atlas: &atlas::Atlas) -> texture::Texture {
+        self.try_create_atlas_texture(atlas)
+            .unwrap_or_else(|e| panic!("Failed to create atlas texture: {}", e))
+    }
+
+    pub fn try_create_atlas_texture(
+        &mut self,
+        atlas: &atlas::Atlas,
+    ) -> Result<texture::Texture, error::EngineError> {
+        let image = image::DynamicImage::ImageRgba8(atlas.image().clone());
+        texture::Texture::from_image(self.device, self.queue, &image, Some("atlas"))
+    }
+
+    // Read a texture back from the GPU, eg. to save a screenshot or compare against a reference.
+    pub fn capture_texture(&mut self, texture: &texture::Texture) -> image::RgbaImage {
+        self.try_capture_texture(texture)
+            .unwrap_or_else(|e| panic!("Failed to capture texture: {}", e))
+    }
+
+    pub fn try_capture_texture(
+        &mut self,
+        texture: &texture::Texture,
+    ) -> Result<image::RgbaImage, error::EngineError> {
+        texture.to_image(self.device, self.queue)
     }
 
     pub fn get_camera(&mut self, handle: camera::CameraHandle) -> &mut camera::Camera {
         &mut self.cameras[handle.0]
     }
+
+    // Replace the bindings used by Input's action and axis queries.
+    pub fn set_input_map(&mut self, input_map: input::InputMap) {
+        self.requests.input_map = Some(input_map);
+    }
+
+    // Exit once the current frame finishes, after calling the game's shutdown function.
+    pub fn request_exit(&mut self) {
+        self.requests.exit = true;
+    }
+
+    // Speed up or slow down both fixed and variable rate updates, 1 is normal speed.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.requests.time_scale = Some(time_scale);
+    }
+
+    // Stop running fixed updates, update still runs but with a delta time of 0.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.requests.paused = Some(paused);
+    }
+
+    // Run one fixed update on the next frame while paused.
+    pub fn step_fixed_update(&mut self) {
+        self.requests.fixed_steps += 1;
+    }
+
+    // Whether the window is currently fullscreen, always false when running headlessly.
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    // The window changes below take effect on the next iteration of the event loop.
+    pub fn set_fullscreen(&mut self, window_mode: config::WindowMode) {
+        self.requests.window.window_mode = Some(window_mode);
+    }
+
+    // Set the window's logical size, this is ignored while the window is fullscreen.
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.requests.window.size = Some((width, height));
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.requests.window.cursor_visible = Some(visible);
+    }
+
+    // Keep the cursor inside of the window, if the platform supports it.
+    pub fn set_cursor_grab(&mut self, grab: bool) {
+        self.requests.window.cursor_grab = Some(grab);
+    }
 }
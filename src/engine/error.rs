@@ -0,0 +1,131 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum EngineError {
+    Io(io::Error),
+    ImageDecode(image::ImageError),
+    // A file in res/ couldn't be loaded, wrapping the reason why.
+    Resource {
+        res_path: String,
+        error: Box<EngineError>,
+    },
+    // The shader failed to compile, or the pipeline using it failed validation.
+    Shader {
+        res_path: String,
+        message: String,
+    },
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    // The surface doesn't support any formats with the adapter.
+    IncompatibleSurface,
+    Surface(wgpu::SurfaceError),
+    // The texture couldn't be created, eg. because it's larger than the device allows.
+    Texture {
+        label: Option<String>,
+        message: String,
+    },
+    // Textures can only be read back in 8-bit RGBA or BGRA formats.
+    UnsupportedReadbackFormat(wgpu::TextureFormat),
+    BufferMap(wgpu::BufferAsyncError),
+    EmptyTextureArray,
+    // Without binding arrays, every texture in an array has to share the same size and format.
+    // Textures of different sizes can be packed into an atlas instead.
+    MismatchedTextureArray,
+    // The asset was unloaded, so its handle no longer refers to anything.
+    InvalidHandle,
+}
+
+impl EngineError {
+    pub fn resource(res_path: &str, error: impl Into<EngineError>) -> Self {
+        Self::Resource {
+            res_path: res_path.into(),
+            error: Box::new(error.into()),
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::ImageDecode(e) => write!(f, "Failed to decode image: {}", e),
+            Self::Resource { res_path, error } => {
+                write!(f, "Failed to load resource {}: {}", res_path, error)
+            }
+            Self::Shader { res_path, message } => {
+                write!(f, "Invalid shader {}: {}", res_path, message)
+            }
+            Self::NoAdapter => write!(f, "No suitable graphics adapter was found"),
+            Self::RequestDevice(e) => write!(f, "Failed to request device: {}", e),
+            Self::IncompatibleSurface => {
+                write!(f, "The window's surface is incompatible with the adapter")
+            }
+            Self::Surface(e) => write!(f, "Surface error: {}", e),
+            Self::Texture { label, message } => write!(
+                f,
+                "Failed to create texture {}: {}",
+                label.as_deref().unwrap_or("[no label]"),
+                message
+            ),
+            Self::UnsupportedReadbackFormat(format) => {
+                write!(f, "Can't read back texture with format: {:?}", format)
+            }
+            Self::BufferMap(e) => write!(f, "Failed to map buffer: {}", e),
+            Self::EmptyTextureArray => {
+                write!(
+                    f,
+                    "Attempted to create a texture array without any textures"
+                )
+            }
+            Self::InvalidHandle => write!(f, "Asset handle refers to an unloaded asset"),
+            Self::MismatchedTextureArray => write!(
+                f,
+                "Textures in an array must share a size and format on this adapter, use an atlas for textures of different sizes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::ImageDecode(e) => Some(e),
+            Self::Resource { error, .. } => Some(error.as_ref()),
+            Self::RequestDevice(e) => Some(e),
+            Self::Surface(e) => Some(e),
+            Self::BufferMap(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EngineError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<image::ImageError> for EngineError {
+    fn from(e: image::ImageError) -> Self {
+        Self::ImageDecode(e)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for EngineError {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        Self::RequestDevice(e)
+    }
+}
+
+impl From<wgpu::SurfaceError> for EngineError {
+    fn from(e: wgpu::SurfaceError) -> Self {
+        Self::Surface(e)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for EngineError {
+    fn from(e: wgpu::BufferAsyncError) -> Self {
+        Self::BufferMap(e)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::engine::{engine_handle, input, render_handle};
+use crate::engine::{config, engine_handle, input, render_handle};
 
 pub trait Game {
     fn start(&mut self, _handle: &mut engine_handle::EngineHandle) {}
@@ -11,10 +11,20 @@ pub trait Game {
     }
     fn fixed_update(&mut self, _input: &input::Input, _handle: &mut engine_handle::EngineHandle) {}
     fn render(&mut self, _handle: &mut render_handle::RenderHandle) {}
+    // Called when the window's close button is pressed. Games can override this to
+    // confirm before quitting, the default is to exit right away.
+    fn on_close_requested(&mut self, handle: &mut engine_handle::EngineHandle) {
+        handle.request_exit();
+    }
+    // Called once before the engine exits.
+    fn shutdown(&mut self, _handle: &mut engine_handle::EngineHandle) {}
     fn get_fixed_update_rate(&self) -> u32 {
         60
     }
     fn get_name(&self) -> String {
         "LowRezJam 2022".into()
     }
+    fn get_config(&self) -> config::EngineConfig {
+        config::EngineConfig::default()
+    }
 }
@@ -0,0 +1,97 @@
+use crate::engine::{input, state};
+
+// Reads gamepad events from gilrs and forwards them to the engine's input handlers.
+pub struct GamepadPoller {
+    gilrs: gilrs::Gilrs,
+    reported_initial_gamepads: bool,
+}
+
+impl GamepadPoller {
+    pub fn new() -> Option<Self> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(Self {
+                gilrs,
+                reported_initial_gamepads: false,
+            }),
+            Err(e) => {
+                eprintln!("Failed to initialize gamepad support: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn poll(&mut self, state: &mut state::State) {
+        // Gamepads that were plugged in before the game started don't get a connect event.
+        if !self.reported_initial_gamepads {
+            self.reported_initial_gamepads = true;
+
+            for (id, _) in self.gilrs.gamepads() {
+                state.gamepad_event(input::GamepadEvent::Connected(id.into()));
+            }
+        }
+
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            let id: input::GamepadId = id.into();
+
+            let gamepad_event = match event {
+                gilrs::EventType::Connected => Some(input::GamepadEvent::Connected(id)),
+                gilrs::EventType::Disconnected => Some(input::GamepadEvent::Disconnected(id)),
+                gilrs::EventType::ButtonPressed(button, _) => convert_button(button).map(|b| {
+                    input::GamepadEvent::ButtonChanged(id, b, winit::event::ElementState::Pressed)
+                }),
+                gilrs::EventType::ButtonReleased(button, _) => convert_button(button).map(|b| {
+                    input::GamepadEvent::ButtonChanged(id, b, winit::event::ElementState::Released)
+                }),
+                gilrs::EventType::AxisChanged(axis, value, _) => {
+                    convert_axis(axis).map(|a| input::GamepadEvent::AxisChanged(id, a, value))
+                }
+                _ => None,
+            };
+
+            if let Some(gamepad_event) = gamepad_event {
+                state.gamepad_event(gamepad_event);
+            }
+        }
+    }
+}
+
+fn convert_button(button: gilrs::Button) -> Option<input::GamepadButton> {
+    use input::GamepadButton;
+
+    match button {
+        gilrs::Button::South => Some(GamepadButton::South),
+        gilrs::Button::East => Some(GamepadButton::East),
+        gilrs::Button::North => Some(GamepadButton::North),
+        gilrs::Button::West => Some(GamepadButton::West),
+        gilrs::Button::LeftTrigger => Some(GamepadButton::LeftTrigger),
+        gilrs::Button::LeftTrigger2 => Some(GamepadButton::LeftTrigger2),
+        gilrs::Button::RightTrigger => Some(GamepadButton::RightTrigger),
+        gilrs::Button::RightTrigger2 => Some(GamepadButton::RightTrigger2),
+        gilrs::Button::Select => Some(GamepadButton::Select),
+        gilrs::Button::Start => Some(GamepadButton::Start),
+        gilrs::Button::Mode => Some(GamepadButton::Mode),
+        gilrs::Button::LeftThumb => Some(GamepadButton::LeftThumb),
+        gilrs::Button::RightThumb => Some(GamepadButton::RightThumb),
+        gilrs::Button::DPadUp => Some(GamepadButton::DPadUp),
+        gilrs::Button::DPadDown => Some(GamepadButton::DPadDown),
+        gilrs::Button::DPadLeft => Some(GamepadButton::DPadLeft),
+        gilrs::Button::DPadRight => Some(GamepadButton::DPadRight),
+        _ => None,
+    }
+}
+
+fn convert_axis(axis: gilrs::Axis) -> Option<input::GamepadAxis> {
+    use input::GamepadAxis;
+
+    match axis {
+        gilrs::Axis::LeftStickX => Some(GamepadAxis::LeftStickX),
+        gilrs::Axis::LeftStickY => Some(GamepadAxis::LeftStickY),
+        gilrs::Axis::LeftZ => Some(GamepadAxis::LeftZ),
+        gilrs::Axis::RightStickX => Some(GamepadAxis::RightStickX),
+        gilrs::Axis::RightStickY => Some(GamepadAxis::RightStickY),
+        gilrs::Axis::RightZ => Some(GamepadAxis::RightZ),
+        gilrs::Axis::DPadX => Some(GamepadAxis::DPadX),
+        gilrs::Axis::DPadY => Some(GamepadAxis::DPadY),
+        _ => None,
+    }
+}
@@ -0,0 +1,211 @@
+use crate::engine::{game, replay, start_headless_game, vfs};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_GOLDEN_DIR: &str = "tests/golden";
+// Set to 1 to save the output of golden tests as their new reference images.
+pub const UPDATE_GOLDENS_VAR: &str = "UPDATE_GOLDENS";
+
+#[derive(Debug)]
+pub enum GoldenResult {
+    Matched,
+    // The reference image was missing or didn't match, and UPDATE_GOLDENS=1 was set,
+    // so the output was saved as the new reference.
+    Updated(PathBuf),
+    Mismatched {
+        mismatched_pixels: u32,
+        actual_path: PathBuf,
+        diff_path: PathBuf,
+    },
+}
+
+// Runs a game headlessly for a set number of fixed updates, then compares the final
+// frame against a reference PNG. Games should be seeded so that their output is repeatable.
+pub struct GoldenTest {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub fixed_updates: u32,
+    // The largest per-channel difference that still counts as a matching pixel.
+    pub tolerance: u8,
+    // Key events that are fed to the game right before the fixed update with the matching tick.
+    pub script: Vec<replay::RecordedInput>,
+    pub golden_dir: PathBuf,
+    pub force_fallback_adapter: bool,
+}
+
+impl GoldenTest {
+    pub fn new(name: &str, fixed_updates: u32) -> Self {
+        Self {
+            name: name.into(),
+            width: 64,
+            height: 64,
+            fixed_updates,
+            tolerance: 2,
+            script: Vec::new(),
+            golden_dir: DEFAULT_GOLDEN_DIR.into(),
+            force_fallback_adapter: false,
+        }
+    }
+
+    // Fails with NotFound if the reference image doesn't exist, unless UPDATE_GOLDENS=1 is set.
+    pub fn run(&self, game: Box<dyn game::Game>) -> Result<GoldenResult, io::Error> {
+        let actual = self.render(game);
+        let golden_file = format!("{}.png", self.name);
+        let golden_path = self.golden_dir.join(&golden_file);
+        let update_goldens = std::env::var(UPDATE_GOLDENS_VAR).as_deref() == Ok("1");
+
+        // Goldens are read through a mount like any other file, but updated ones are written to disk.
+        let golden_vfs = vfs::Vfs::from_sources(&[vfs::MountSource::Dir(self.golden_dir.clone())])
+            .map_err(io::Error::other)?;
+        let expected = match golden_vfs.read(&golden_file) {
+            Ok(bytes) => image::load_from_memory(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .to_rgba8(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && update_goldens => {
+                std::fs::create_dir_all(&self.golden_dir)?;
+                save_image(&actual, &golden_path)?;
+                return Ok(GoldenResult::Updated(golden_path));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "Missing golden {}, run with {}=1 to create it",
+                        golden_path.display(),
+                        UPDATE_GOLDENS_VAR
+                    ),
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+
+        let (mismatched_pixels, diff) = compare_images(&expected, &actual, self.tolerance);
+
+        if mismatched_pixels == 0 {
+            return Ok(GoldenResult::Matched);
+        }
+
+        if update_goldens {
+            save_image(&actual, &golden_path)?;
+            return Ok(GoldenResult::Updated(golden_path));
+        }
+
+        let actual_path = self.golden_dir.join(format!("{}.actual.png", self.name));
+        let diff_path = self.golden_dir.join(format!("{}.diff.png", self.name));
+        save_image(&actual, &actual_path)?;
+        save_image(&diff, &diff_path)?;
+
+        Ok(GoldenResult::Mismatched {
+            mismatched_pixels,
+            actual_path,
+            diff_path,
+        })
+    }
+
+    // Run the game's update loop with the scripted input, and return the last frame.
+    pub fn render(&self, game: Box<dyn game::Game>) -> image::RgbaImage {
+        let mut state =
+            start_headless_game(game, self.width, self.height, self.force_fallback_adapter);
+        let fixed_update_delta = 1.0 / state.fixed_update_rate() as f32;
+        state.start_replay(replay::InputReplay::new(self.script.clone()));
+
+        for _ in 0..self.fixed_updates {
+            state.fixed_update();
+            state.update(fixed_update_delta);
+        }
+
+        state.render().expect("Failed to render golden test frame");
+        state
+            .capture_frame()
+            .expect("Golden tests require a headless state")
+    }
+}
+
+// Returns the number of pixels that differ by more than the tolerance, along with an
+// image that highlights them in red over a faded copy of the actual output.
+pub fn compare_images(
+    expected: &image::RgbaImage,
+    actual: &image::RgbaImage,
+    tolerance: u8,
+) -> (u32, image::RgbaImage) {
+    let width = expected.width().max(actual.width());
+    let height = expected.height().max(actual.height());
+    let mut diff = image::RgbaImage::new(width, height);
+    let mut mismatched_pixels = 0;
+
+    for (x, y, diff_pixel) in diff.enumerate_pixels_mut() {
+        let in_bounds = x < expected.width()
+            && y < expected.height()
+            && x < actual.width()
+            && y < actual.height();
+
+        let matches = in_bounds && {
+            let expected_pixel = expected.get_pixel(x, y);
+            let actual_pixel = actual.get_pixel(x, y);
+
+            expected_pixel
+                .0
+                .iter()
+                .zip(actual_pixel.0.iter())
+                .all(|(e, a)| e.abs_diff(*a) <= tolerance)
+        };
+
+        *diff_pixel = if matches {
+            let faded = actual.get_pixel(x, y).0.map(|c| c / 4);
+            image::Rgba([faded[0], faded[1], faded[2], 255])
+        } else {
+            mismatched_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        };
+    }
+
+    (mismatched_pixels, diff)
+}
+
+fn save_image(image: &image::RgbaImage, path: &Path) -> Result<(), io::Error> {
+    image.save(path).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_image(width: u32, height: u32, color: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba(color))
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = solid_image(4, 4, [10, 20, 30, 255]);
+        let (mismatched_pixels, diff) = compare_images(&image, &image, 0);
+
+        assert_eq!(mismatched_pixels, 0);
+        assert_eq!(diff.get_pixel(0, 0).0, [2, 5, 7, 255]);
+    }
+
+    #[test]
+    fn differences_within_the_tolerance_match() {
+        let expected = solid_image(4, 4, [10, 20, 30, 255]);
+        let mut actual = solid_image(4, 4, [12, 18, 30, 255]);
+
+        assert_eq!(compare_images(&expected, &actual, 2).0, 0);
+
+        actual.put_pixel(1, 2, image::Rgba([13, 20, 30, 255]));
+        let (mismatched_pixels, diff) = compare_images(&expected, &actual, 2);
+
+        assert_eq!(mismatched_pixels, 1);
+        assert_eq!(diff.get_pixel(1, 2).0, [255, 0, 0, 255]);
+        assert_ne!(diff.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn pixels_outside_either_image_mismatch() {
+        let expected = solid_image(4, 2, [0, 0, 0, 255]);
+        let actual = solid_image(2, 4, [0, 0, 0, 255]);
+        let (mismatched_pixels, diff) = compare_images(&expected, &actual, 255);
+
+        assert_eq!(diff.dimensions(), (4, 4));
+        assert_eq!(mismatched_pixels, 16 - 4);
+    }
+}
@@ -0,0 +1,488 @@
+use crate::engine::{assets, pipeline, texture, texture_array, vfs};
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+// Without the hot_reload feature nothing ever reloads, so nothing is tracked either.
+const WATCH_RESOURCES: bool = cfg!(feature = "hot_reload");
+
+struct TextureTarget {
+    texture: Weak<wgpu::Texture>,
+    layer: u32,
+    width: u32,
+    height: u32,
+}
+
+struct PipelineTarget {
+    pipeline: assets::Handle<wgpu::RenderPipeline>,
+    builder: pipeline::PipelineBuilder,
+    bind_group_count: u32,
+}
+
+// Keeps track of which GPU resources were created from which files in res/, so that
+// they can be updated when those files change. Textures are only weakly referenced,
+// so dropping them as usual is enough to stop reloading them.
+#[derive(Default)]
+pub struct ReloadRegistry {
+    textures: HashMap<String, Vec<TextureTarget>>,
+    pipelines: Vec<PipelineTarget>,
+}
+
+impl ReloadRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watch_texture(&mut self, texture: &texture::Texture) {
+        if let Some(res_path) = texture.res_path() {
+            self.watch_texture_layer(
+                res_path,
+                texture.shared_texture(),
+                0,
+                texture.width(),
+                texture.height(),
+            );
+        }
+    }
+
+    pub fn watch_texture_array(
+        &mut self,
+        texture_array: &texture_array::TextureArray,
+        res_paths: &[Option<String>],
+        (width, height): (u32, u32),
+    ) {
+        let layered_texture = match texture_array.layered_texture() {
+            Some(layered_texture) => layered_texture,
+            // Textures in binding arrays are kept alive by the array, and are already watched.
+            None => return,
+        };
+
+        for (layer, res_path) in res_paths.iter().enumerate() {
+            if let Some(res_path) = res_path {
+                self.watch_texture_layer(res_path, layered_texture, layer as u32, width, height);
+            }
+        }
+    }
+
+    fn watch_texture_layer(
+        &mut self,
+        res_path: &str,
+        texture: &Arc<wgpu::Texture>,
+        layer: u32,
+        width: u32,
+        height: u32,
+    ) {
+        if !WATCH_RESOURCES {
+            return;
+        }
+
+        let targets = self.textures.entry(res_path.into()).or_default();
+        // Forget dropped textures here too, so files that never change don't keep growing.
+        targets.retain(|t| t.texture.strong_count() > 0);
+        targets.push(TextureTarget {
+            texture: Arc::downgrade(texture),
+            layer,
+            width,
+            height,
+        });
+    }
+
+    pub fn watch_pipeline(
+        &mut self,
+        pipeline: assets::Handle<wgpu::RenderPipeline>,
+        builder: pipeline::PipelineBuilder,
+        bind_group_count: u32,
+    ) {
+        if !WATCH_RESOURCES {
+            return;
+        }
+
+        self.pipelines.push(PipelineTarget {
+            pipeline,
+            builder,
+            bind_group_count,
+        });
+    }
+
+    // Update everything that was created from the file. Errors are logged, leaving
+    // the last working version of each resource in place.
+    pub fn reload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        vfs: &vfs::Vfs,
+        assets: &mut assets::AssetServer,
+        res_path: &str,
+    ) {
+        self.reload_textures(queue, vfs, res_path);
+        self.reload_shaders(device, vfs, assets, res_path);
+        self.reload_pipelines(device, format, vfs, assets, res_path);
+    }
+
+    fn reload_textures(&mut self, queue: &wgpu::Queue, vfs: &vfs::Vfs, res_path: &str) {
+        let targets = match self.textures.get_mut(res_path) {
+            Some(targets) => targets,
+            None => return,
+        };
+
+        targets.retain(|t| t.texture.strong_count() > 0);
+
+        if targets.is_empty() {
+            self.textures.remove(res_path);
+            return;
+        }
+
+        let image = match vfs.read_image(res_path) {
+            Ok(image) => image.to_rgba8(),
+            Err(e) => {
+                log::warn!("Failed to reload texture: {}", e);
+                return;
+            }
+        };
+
+        for target in targets.iter() {
+            let texture = match target.texture.upgrade() {
+                Some(texture) => texture,
+                None => continue,
+            };
+
+            if image.dimensions() != (target.width, target.height) {
+                log::warn!(
+                    "Can't reload texture {}, its size changed from {}x{} to {}x{}",
+                    res_path,
+                    target.width,
+                    target.height,
+                    image.width(),
+                    image.height()
+                );
+                continue;
+            }
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: target.layer,
+                    },
+                },
+                &image,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * target.width),
+                    rows_per_image: std::num::NonZeroU32::new(target.height),
+                },
+                wgpu::Extent3d {
+                    width: target.width,
+                    height: target.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        log::info!("Reloaded texture {}", res_path);
+    }
+
+    fn reload_shaders(
+        &mut self,
+        device: &wgpu::Device,
+        vfs: &vfs::Vfs,
+        assets: &mut assets::AssetServer,
+        res_path: &str,
+    ) {
+        // Shader assets are stored under the path they were requested with, which
+        // differs from the file that was loaded when using fallback shaders.
+        let requested_path = res_path.replacen(".fallback.", ".", 1);
+
+        for path in [res_path, requested_path.as_str()] {
+            let handle = match assets.handle_for_path::<assets::Shader>(path) {
+                Some(handle) => handle,
+                None => continue,
+            };
+
+            let is_loaded_from_file = assets.get(handle).map(|s| s.res_path()) == Some(res_path);
+            if !is_loaded_from_file {
+                continue;
+            }
+
+            match pipeline::load_shader(device, vfs, path) {
+                Ok(shader) => {
+                    if let Some(old_shader) = assets.get_mut(handle) {
+                        *old_shader = shader;
+                    }
+                    log::info!("Reloaded shader {}", res_path);
+                }
+                Err(e) => log::error!("Failed to reload shader: {}", e),
+            }
+        }
+    }
+
+    fn reload_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        vfs: &vfs::Vfs,
+        assets: &mut assets::AssetServer,
+        res_path: &str,
+    ) {
+        self.pipelines.retain(|p| assets.get(p.pipeline).is_some());
+
+        for target in &self.pipelines {
+            if pipeline::resolve_shader_path(device, target.builder.shader_res_path()) != res_path {
+                continue;
+            }
+
+            let old_pipeline = match assets.get(target.pipeline) {
+                Some(pipeline) => pipeline,
+                None => continue,
+            };
+
+            // Reuse the old pipeline's layouts so that existing bind groups stay compatible.
+            let layouts = (0..target.bind_group_count)
+                .map(|i| old_pipeline.get_bind_group_layout(i))
+                .collect::<Vec<_>>();
+            let layouts = layouts.iter().collect::<Vec<_>>();
+
+            match target.builder.build(device, format, vfs, &layouts) {
+                Ok(new_pipeline) => {
+                    if let Some(old_pipeline) = assets.get_mut(target.pipeline) {
+                        *old_pipeline = new_pipeline;
+                    }
+                    log::info!("Rebuilt pipeline using {}", res_path);
+                }
+                Err(e) => log::error!("Failed to rebuild pipeline: {}", e),
+            }
+        }
+    }
+}
+
+// Watches the VFS's directory mounts for changes, so that they can be reloaded while
+// the game is running.
+#[cfg(feature = "hot_reload")]
+pub struct ResWatcher {
+    _watcher: notify::RecommendedWatcher,
+    receiver: std::sync::mpsc::Receiver<std::path::PathBuf>,
+    dirs: Vec<std::path::PathBuf>,
+}
+
+#[cfg(feature = "hot_reload")]
+impl ResWatcher {
+    pub fn new(vfs: &vfs::Vfs) -> Option<Self> {
+        use notify::Watcher;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                if event.kind.is_create() || event.kind.is_modify() {
+                    for path in event.paths {
+                        let _ = sender.send(path);
+                    }
+                }
+            }
+        });
+
+        let mut watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                log::warn!("Failed to create res watcher: {}", e);
+                return None;
+            }
+        };
+
+        let mut dirs = Vec::new();
+        for dir in vfs.dirs() {
+            match watcher.watch(dir, notify::RecursiveMode::Recursive) {
+                Ok(()) => dirs.push(dir.canonicalize().unwrap_or_else(|_| dir.into())),
+                Err(e) => log::warn!("Failed to watch {}: {}", dir.display(), e),
+            }
+        }
+
+        Some(Self {
+            _watcher: watcher,
+            receiver,
+            dirs,
+        })
+    }
+
+    // Res paths of the files that changed since the last call, without duplicates.
+    pub fn changed_res_paths(&self) -> Vec<String> {
+        let mut res_paths = Vec::new();
+
+        for path in self.receiver.try_iter() {
+            let path = path.canonicalize().unwrap_or(path);
+            let res_path = match self.dirs.iter().find_map(|dir| path.strip_prefix(dir).ok()) {
+                Some(res_path) => res_path.to_string_lossy().replace('\\', "/"),
+                None => continue,
+            };
+
+            if !res_paths.contains(&res_path) {
+                res_paths.push(res_path);
+            }
+        }
+
+        res_paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::texture::tests::create_test_device;
+    #[cfg(feature = "hot_reload")]
+    use crate::engine::{
+        camera, config, engine_handle, game, model, render_handle,
+        texture::tests::assert_all_pixels,
+    };
+
+    fn create_texture(device: &wgpu::Device) -> Arc<wgpu::Texture> {
+        texture::Texture::from_dimensions(
+            device,
+            1,
+            1,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::empty(),
+            None,
+        )
+        .unwrap()
+        .shared_texture()
+        .clone()
+    }
+
+    #[cfg(not(feature = "hot_reload"))]
+    #[test]
+    fn nothing_is_watched_without_hot_reload() {
+        let (device, _) = create_test_device();
+        let mut registry = ReloadRegistry::new();
+        let texture = create_texture(&device);
+
+        registry.watch_texture_layer("a.png", &texture, 0, 1, 1);
+        assert!(registry.textures.is_empty());
+    }
+
+    #[cfg(feature = "hot_reload")]
+    #[test]
+    fn dropped_textures_are_forgotten_when_watching() {
+        let (device, _) = create_test_device();
+        let mut registry = ReloadRegistry::new();
+
+        for _ in 0..3 {
+            let texture = create_texture(&device);
+            registry.watch_texture_layer("a.png", &texture, 0, 1, 1);
+        }
+
+        let kept = create_texture(&device);
+        registry.watch_texture_layer("a.png", &kept, 0, 1, 1);
+        assert_eq!(registry.textures["a.png"].len(), 1);
+    }
+
+    // Covers a fullscreen target with one triangle of a solid colour, ignoring its vertices.
+    #[cfg(feature = "hot_reload")]
+    fn solid_color_shader(color: &str) -> String {
+        format!(
+            "@vertex
+            fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {{
+                let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+                return vec4<f32>(uv * 2.0 - 1.0, 0.5, 1.0);
+            }}
+
+            @fragment
+            fn fs_main() -> @location(0) vec4<f32> {{
+                return vec4<f32>({});
+            }}",
+            color
+        )
+    }
+
+    #[cfg(feature = "hot_reload")]
+    struct PipelineGame {
+        res_dir: std::path::PathBuf,
+        state: Option<(
+            camera::CameraHandle,
+            assets::Handle<wgpu::RenderPipeline>,
+            model::Model,
+            wgpu::Buffer,
+        )>,
+    }
+
+    #[cfg(feature = "hot_reload")]
+    impl game::Game for PipelineGame {
+        fn start(&mut self, handle: &mut engine_handle::EngineHandle) {
+            let camera = handle.create_camera(
+                (0.0, 0.0, 1.0).into(),
+                (0.0, 0.0, 0.0).into(),
+                cgmath::Vector3::unit_y(),
+                Box::new(camera::OrthographicProjection {
+                    width: 2.0,
+                    height: 2.0,
+                    fixed_aspect_ratio: true,
+                    z_near: 0.1,
+                    z_far: 10.0,
+                }),
+                None,
+                None,
+            );
+            let pipeline = handle.create_pipeline("color.wgsl", &[], None);
+            let model = handle.create_model(&[bytemuck::Zeroable::zeroed(); 3], &[0u16, 1, 2]);
+            let instances = handle.create_instance_buffer_from_raw(&[bytemuck::Zeroable::zeroed()]);
+
+            self.state = Some((camera, pipeline, model, instances));
+        }
+
+        fn render(&mut self, handle: &mut render_handle::RenderHandle) {
+            let (camera, pipeline, model, instances) = self.state.as_ref().unwrap();
+            let pipeline = handle.get_pipeline(*pipeline);
+            let (mut render_pass, _) = handle.begin_render_pass(*camera, wgpu::Color::BLACK, None);
+
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_vertex_buffer(1, instances.slice(..));
+            render_handle::RenderHandle::draw_model(&mut render_pass, model, 0..1);
+        }
+
+        fn get_config(&self) -> config::EngineConfig {
+            config::EngineConfig {
+                mounts: vec![vfs::MountSource::Dir(self.res_dir.clone())],
+                ..Default::default()
+            }
+        }
+    }
+
+    #[cfg(feature = "hot_reload")]
+    #[test]
+    fn changed_shaders_rebuild_created_pipelines() {
+        let res_dir = std::env::temp_dir().join(format!("hot_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&res_dir).unwrap();
+        let write_shader = |color: &str| {
+            for file in ["color.wgsl", "color.fallback.wgsl"] {
+                std::fs::write(res_dir.join(file), solid_color_shader(color)).unwrap();
+            }
+        };
+
+        write_shader("1.0, 0.0, 0.0, 1.0");
+        let game = PipelineGame {
+            res_dir: res_dir.clone(),
+            state: None,
+        };
+        let mut state = crate::engine::start_headless_game(Box::new(game), 4, 4, true);
+        state.start();
+        state.render().unwrap();
+        assert_all_pixels(
+            &state.capture_frame().unwrap(),
+            image::Rgba([255, 0, 0, 255]),
+        );
+
+        write_shader("0.0, 1.0, 0.0, 1.0");
+        // The watcher reports both files, only the one loaded for this adapter matters.
+        state.reload_res("color.wgsl");
+        state.reload_res("color.fallback.wgsl");
+        state.render().unwrap();
+        assert_all_pixels(
+            &state.capture_frame().unwrap(),
+            image::Rgba([0, 255, 0, 255]),
+        );
+
+        std::fs::remove_dir_all(&res_dir).unwrap();
+    }
+}
@@ -1,11 +1,313 @@
-use std::collections::HashSet;
-use winit::event::{ElementState, VirtualKeyCode};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode};
+
+// Used to convert pixel based scrolling (eg. from touchpads) into lines.
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+const INPUT_MAP_HEADER: &str = "# input map v1";
+
+// How far a gamepad axis has to move before an action bound to it counts as held.
+const ACTION_AXIS_THRESHOLD: f32 = 0.5;
+
+// Look up a key by its variant name, eg. "Space" or "Left".
+pub fn keycode_from_name(name: &str) -> Option<VirtualKeyCode> {
+    variant_from_name(name)
+}
+
+pub fn mouse_button_from_name(name: &str) -> Option<MouseButton> {
+    match name.parse() {
+        Ok(button) => Some(MouseButton::Other(button)),
+        Err(_) => variant_from_name(name),
+    }
+}
+
+// The inverse of mouse_button_from_name, buttons without a name are written as numbers.
+pub fn mouse_button_name(button: MouseButton) -> String {
+    match button {
+        MouseButton::Other(button) => button.to_string(),
+        button => format!("{:?}", button),
+    }
+}
+
+pub fn gamepad_button_from_name(name: &str) -> Option<GamepadButton> {
+    variant_from_name(name)
+}
+
+pub fn gamepad_axis_from_name(name: &str) -> Option<GamepadAxis> {
+    variant_from_name(name)
+}
+
+fn variant_from_name<'de, T: Deserialize<'de>>(name: &'de str) -> Option<T> {
+    T::deserialize(serde::de::value::StrDeserializer::<serde::de::value::Error>::new(name)).ok()
+}
+
+pub type GamepadId = usize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    LeftZ,
+    RightStickX,
+    RightStickY,
+    RightZ,
+    DPadX,
+    DPadY,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId),
+    Disconnected(GamepadId),
+    ButtonChanged(GamepadId, GamepadButton, ElementState),
+    AxisChanged(GamepadId, GamepadAxis, f32),
+}
+
+// Any input that the engine passes on to Input. Fixed updates only receive input
+// through these events, so recording them is enough to replay a session.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key(VirtualKeyCode, ElementState),
+    MouseButton(MouseButton, ElementState),
+    CursorMoved(f32, f32),
+    CursorLeft,
+    MouseWheel(MouseScrollDelta),
+    Character(char),
+    Gamepad(GamepadEvent),
+}
+
+// Text typed since the last update, kept separate from key states.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextInput {
+    Character(char),
+    Backspace,
+    Enter,
+}
+
+// Something that can be bound to an action or axis. Gamepad inputs match any gamepad.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputSource {
+    Key(VirtualKeyCode),
+    MouseButton(MouseButton),
+    GamepadButton(GamepadButton),
+    GamepadAxis(GamepadAxis),
+}
+
+impl InputSource {
+    fn to_config_string(self) -> String {
+        match self {
+            InputSource::Key(keycode) => format!("key:{:?}", keycode),
+            InputSource::MouseButton(button) => format!("mouse:{}", mouse_button_name(button)),
+            InputSource::GamepadButton(button) => format!("gamepad:{:?}", button),
+            InputSource::GamepadAxis(axis) => format!("gamepad_axis:{:?}", axis),
+        }
+    }
+
+    fn from_config_string(config_string: &str) -> Option<Self> {
+        let (kind, name) = config_string.split_once(':')?;
+
+        match kind {
+            "key" => keycode_from_name(name).map(InputSource::Key),
+            "mouse" => mouse_button_from_name(name).map(InputSource::MouseButton),
+            "gamepad" => gamepad_button_from_name(name).map(InputSource::GamepadButton),
+            "gamepad_axis" => gamepad_axis_from_name(name).map(InputSource::GamepadAxis),
+            _ => None,
+        }
+    }
+}
+
+// One input contributing to an axis. Buttons add the scale while held, gamepad axes
+// add their value multiplied by the scale.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AxisBinding {
+    pub source: InputSource,
+    pub scale: f32,
+}
+
+// Maps named actions and axes to inputs, so that games don't have to hard-code keys.
+// Bindings are stored as text with one binding per line, eg:
+// "action fire key:Space", "axis move_x -1 key:Left", "axis2 move move_x move_y".
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct InputMap {
+    actions: HashMap<String, Vec<InputSource>>,
+    axes: HashMap<String, Vec<AxisBinding>>,
+    axes2: HashMap<String, (String, String)>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind_action(&mut self, action: &str, source: InputSource) {
+        self.actions.entry(action.into()).or_default().push(source);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, source: InputSource, scale: f32) {
+        self.axes
+            .entry(axis.into())
+            .or_default()
+            .push(AxisBinding { source, scale });
+    }
+
+    // Bind a 2D axis to two existing 1D axes.
+    pub fn bind_axis2(&mut self, axis: &str, x_axis: &str, y_axis: &str) {
+        self.axes2
+            .insert(axis.into(), (x_axis.into(), y_axis.into()));
+    }
+
+    pub fn clear_action(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    pub fn clear_axis(&mut self, axis: &str) {
+        self.axes.remove(axis);
+    }
+
+    pub fn action_bindings(&self, action: &str) -> &[InputSource] {
+        self.actions.get(action).map_or(&[], |b| b.as_slice())
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map_or(&[], |b| b.as_slice())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        Self::read(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    fn read(reader: impl BufRead) -> Result<Self, io::Error> {
+        let mut input_map = Self::new();
+
+        for (line_i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if input_map.parse_line(line).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid input binding on line {}: {}", line_i + 1, line),
+                ));
+            }
+        }
+
+        Ok(input_map)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Option<()> {
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+
+        match parts.as_slice() {
+            ["action", action, source] => {
+                self.bind_action(action, InputSource::from_config_string(source)?)
+            }
+            ["axis", axis, scale, source] => self.bind_axis(
+                axis,
+                InputSource::from_config_string(source)?,
+                scale.parse().ok()?,
+            ),
+            ["axis2", axis, x_axis, y_axis] => self.bind_axis2(axis, x_axis, y_axis),
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file)?;
+
+        file.flush()
+    }
+
+    fn write(&self, mut file: impl Write) -> Result<(), io::Error> {
+        writeln!(file, "{}", INPUT_MAP_HEADER)?;
+
+        // Sort by name so that saved files are stable between runs.
+        let mut actions = self.actions.iter().collect::<Vec<_>>();
+        actions.sort_by_key(|a| a.0);
+        for (action, sources) in actions {
+            for source in sources {
+                writeln!(file, "action {} {}", action, source.to_config_string())?;
+            }
+        }
+
+        let mut axes = self.axes.iter().collect::<Vec<_>>();
+        axes.sort_by_key(|a| a.0);
+        for (axis, bindings) in axes {
+            for binding in bindings {
+                writeln!(
+                    file,
+                    "axis {} {} {}",
+                    axis,
+                    binding.scale,
+                    binding.source.to_config_string()
+                )?;
+            }
+        }
+
+        let mut axes2 = self.axes2.iter().collect::<Vec<_>>();
+        axes2.sort_by_key(|a| a.0);
+        for (axis, (x_axis, y_axis)) in axes2 {
+            writeln!(file, "axis2 {} {} {}", axis, x_axis, y_axis)?;
+        }
+
+        Ok(())
+    }
+}
 
 // Keeps track of user input.
 pub struct Input {
     pressed_keys: HashSet<VirtualKeyCode>,
     released_keys: HashSet<VirtualKeyCode>,
     held_keys: HashSet<VirtualKeyCode>,
+    pressed_mouse_buttons: HashSet<MouseButton>,
+    released_mouse_buttons: HashSet<MouseButton>,
+    held_mouse_buttons: HashSet<MouseButton>,
+    cursor_position: Option<cgmath::Vector2<f32>>,
+    scroll_delta: cgmath::Vector2<f32>,
+    pressed_gamepad_buttons: HashSet<(GamepadId, GamepadButton)>,
+    released_gamepad_buttons: HashSet<(GamepadId, GamepadButton)>,
+    held_gamepad_buttons: HashSet<(GamepadId, GamepadButton)>,
+    gamepad_axes: HashMap<(GamepadId, GamepadAxis), f32>,
+    connected_gamepads: HashSet<GamepadId>,
+    gamepad_connection_events: Vec<GamepadEvent>,
+    input_map: InputMap,
+    text_input: Vec<TextInput>,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Input {
@@ -14,19 +316,46 @@ impl Input {
             pressed_keys: HashSet::new(),
             released_keys: HashSet::new(),
             held_keys: HashSet::new(),
+            pressed_mouse_buttons: HashSet::new(),
+            released_mouse_buttons: HashSet::new(),
+            held_mouse_buttons: HashSet::new(),
+            cursor_position: None,
+            scroll_delta: cgmath::Vector2::new(0.0, 0.0),
+            pressed_gamepad_buttons: HashSet::new(),
+            released_gamepad_buttons: HashSet::new(),
+            held_gamepad_buttons: HashSet::new(),
+            gamepad_axes: HashMap::new(),
+            connected_gamepads: HashSet::new(),
+            gamepad_connection_events: Vec::new(),
+            input_map: InputMap::new(),
+            text_input: Vec::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Key(keycode, state) => self.key_state_changed(keycode, state),
+            InputEvent::MouseButton(button, state) => {
+                self.mouse_button_state_changed(button, state)
+            }
+            InputEvent::CursorMoved(x, y) => self.cursor_moved(x, y),
+            InputEvent::CursorLeft => self.cursor_left(),
+            InputEvent::MouseWheel(delta) => self.mouse_wheel_scrolled(delta),
+            InputEvent::Character(c) => self.character_received(c),
+            InputEvent::Gamepad(event) => self.gamepad_event(event),
         }
     }
 
     pub fn was_key_pressed(&self, keycode: VirtualKeyCode) -> bool {
-        return self.pressed_keys.contains(&keycode);
+        self.pressed_keys.contains(&keycode)
     }
 
     pub fn was_key_released(&self, keycode: VirtualKeyCode) -> bool {
-        return self.released_keys.contains(&keycode);
+        self.released_keys.contains(&keycode)
     }
 
     pub fn is_key_held(&self, keycode: VirtualKeyCode) -> bool {
-        return self.held_keys.contains(&keycode);
+        self.held_keys.contains(&keycode)
     }
 
     pub fn key_state_changed(&mut self, keycode: VirtualKeyCode, state: ElementState) {
@@ -43,8 +372,334 @@ impl Input {
         }
     }
 
+    pub fn was_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_mouse_buttons.contains(&button)
+    }
+
+    pub fn was_mouse_button_released(&self, button: MouseButton) -> bool {
+        self.released_mouse_buttons.contains(&button)
+    }
+
+    pub fn is_mouse_button_held(&self, button: MouseButton) -> bool {
+        self.held_mouse_buttons.contains(&button)
+    }
+
+    // The cursor's position in window pixels, or None if it is outside of the window.
+    // LowResPresenter::cursor_position converts it to the virtual screen's pixels.
+    pub fn cursor_position(&self) -> Option<cgmath::Vector2<f32>> {
+        self.cursor_position
+    }
+
+    // How far the mouse wheel has scrolled since the last update, in lines.
+    pub fn scroll_delta(&self) -> cgmath::Vector2<f32> {
+        self.scroll_delta
+    }
+
+    pub fn was_gamepad_button_pressed(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.pressed_gamepad_buttons.contains(&(id, button))
+    }
+
+    pub fn was_gamepad_button_released(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.released_gamepad_buttons.contains(&(id, button))
+    }
+
+    pub fn is_gamepad_button_held(&self, id: GamepadId, button: GamepadButton) -> bool {
+        self.held_gamepad_buttons.contains(&(id, button))
+    }
+
+    // An axis' value in the range [-1.0, 1.0], or 0.0 if it hasn't moved yet.
+    pub fn gamepad_axis(&self, id: GamepadId, axis: GamepadAxis) -> f32 {
+        *self.gamepad_axes.get(&(id, axis)).unwrap_or(&0.0)
+    }
+
+    pub fn connected_gamepads(&self) -> impl Iterator<Item = GamepadId> + '_ {
+        self.connected_gamepads.iter().copied()
+    }
+
+    // Gamepads that were connected or disconnected since the last update.
+    pub fn gamepad_connection_events(&self) -> &[GamepadEvent] {
+        &self.gamepad_connection_events
+    }
+
+    pub fn gamepad_event(&mut self, event: GamepadEvent) {
+        match event {
+            GamepadEvent::Connected(id) => {
+                self.connected_gamepads.insert(id);
+                self.gamepad_connection_events.push(event);
+            }
+            GamepadEvent::Disconnected(id) => {
+                self.connected_gamepads.remove(&id);
+                self.held_gamepad_buttons
+                    .retain(|(held_id, _)| *held_id != id);
+                self.gamepad_axes.retain(|(axis_id, _), _| *axis_id != id);
+                self.gamepad_connection_events.push(event);
+            }
+            GamepadEvent::ButtonChanged(id, button, ElementState::Pressed) => {
+                if self.held_gamepad_buttons.insert((id, button)) {
+                    self.pressed_gamepad_buttons.insert((id, button));
+                }
+            }
+            GamepadEvent::ButtonChanged(id, button, ElementState::Released) => {
+                self.released_gamepad_buttons.insert((id, button));
+                self.held_gamepad_buttons.remove(&(id, button));
+            }
+            GamepadEvent::AxisChanged(id, axis, value) => {
+                self.gamepad_axes.insert((id, axis), value);
+            }
+        }
+    }
+
+    pub fn input_map(&self) -> &InputMap {
+        &self.input_map
+    }
+
+    pub fn set_input_map(&mut self, input_map: InputMap) {
+        self.input_map = input_map;
+    }
+
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.input_map
+            .action_bindings(action)
+            .iter()
+            .any(|source| match *source {
+                InputSource::Key(keycode) => self.was_key_pressed(keycode),
+                InputSource::MouseButton(button) => self.was_mouse_button_pressed(button),
+                InputSource::GamepadButton(button) => self
+                    .pressed_gamepad_buttons
+                    .iter()
+                    .any(|(_, b)| *b == button),
+                InputSource::GamepadAxis(_) => false,
+            })
+    }
+
+    pub fn action_released(&self, action: &str) -> bool {
+        self.input_map
+            .action_bindings(action)
+            .iter()
+            .any(|source| match *source {
+                InputSource::Key(keycode) => self.was_key_released(keycode),
+                InputSource::MouseButton(button) => self.was_mouse_button_released(button),
+                InputSource::GamepadButton(button) => self
+                    .released_gamepad_buttons
+                    .iter()
+                    .any(|(_, b)| *b == button),
+                InputSource::GamepadAxis(_) => false,
+            })
+    }
+
+    pub fn action_held(&self, action: &str) -> bool {
+        self.input_map
+            .action_bindings(action)
+            .iter()
+            .any(|source| self.source_value(*source).abs() >= ACTION_AXIS_THRESHOLD)
+    }
+
+    // The sum of an axis' bindings, clamped to [-1.0, 1.0].
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.input_map
+            .axis_bindings(axis)
+            .iter()
+            .map(|binding| self.source_value(binding.source) * binding.scale)
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+
+    pub fn axis2(&self, axis: &str) -> cgmath::Vector2<f32> {
+        match self.input_map.axes2.get(axis) {
+            Some((x_axis, y_axis)) => cgmath::Vector2::new(self.axis(x_axis), self.axis(y_axis)),
+            None => cgmath::Vector2::new(0.0, 0.0),
+        }
+    }
+
+    // Buttons are 1.0 while held, gamepad axes use the value from whichever gamepad is
+    // furthest from the center.
+    fn source_value(&self, source: InputSource) -> f32 {
+        let button_value = |held: bool| if held { 1.0 } else { 0.0 };
+
+        match source {
+            InputSource::Key(keycode) => button_value(self.is_key_held(keycode)),
+            InputSource::MouseButton(button) => button_value(self.is_mouse_button_held(button)),
+            InputSource::GamepadButton(button) => {
+                button_value(self.held_gamepad_buttons.iter().any(|(_, b)| *b == button))
+            }
+            InputSource::GamepadAxis(axis) => self
+                .gamepad_axes
+                .iter()
+                .filter(|((_, a), _)| *a == axis)
+                .map(|(_, value)| *value)
+                .fold(0.0, |furthest: f32, value| {
+                    if value.abs() > furthest.abs() {
+                        value
+                    } else {
+                        furthest
+                    }
+                }),
+        }
+    }
+
+    pub fn text_input(&self) -> &[TextInput] {
+        &self.text_input
+    }
+
+    // The characters typed since the last update, ignoring backspace and enter.
+    pub fn typed_text(&self) -> String {
+        self.text_input
+            .iter()
+            .filter_map(|t| match t {
+                TextInput::Character(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Apply this update's typing to a string, eg. for name entry or a console.
+    // Returns true if enter was pressed.
+    pub fn edit_text(&self, text: &mut String) -> bool {
+        let mut entered = false;
+
+        for t in &self.text_input {
+            match t {
+                TextInput::Character(c) => text.push(*c),
+                TextInput::Backspace => {
+                    text.pop();
+                }
+                TextInput::Enter => entered = true,
+            }
+        }
+
+        entered
+    }
+
+    pub fn character_received(&mut self, c: char) {
+        let text_input = match c {
+            // macOS sends delete rather than backspace.
+            '\u{8}' | '\u{7f}' => TextInput::Backspace,
+            '\r' | '\n' => TextInput::Enter,
+            // Other control characters come from shortcuts like ctrl+c, and aren't text.
+            _ if c.is_control() => return,
+            _ => TextInput::Character(c),
+        };
+
+        self.text_input.push(text_input);
+    }
+
+    pub fn mouse_button_state_changed(&mut self, button: MouseButton, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                if self.held_mouse_buttons.insert(button) {
+                    self.pressed_mouse_buttons.insert(button);
+                }
+            }
+            ElementState::Released => {
+                self.released_mouse_buttons.insert(button);
+                self.held_mouse_buttons.remove(&button);
+            }
+        }
+    }
+
+    pub fn cursor_moved(&mut self, x: f32, y: f32) {
+        self.cursor_position = Some(cgmath::Vector2::new(x, y));
+    }
+
+    pub fn cursor_left(&mut self) {
+        self.cursor_position = None;
+    }
+
+    pub fn mouse_wheel_scrolled(&mut self, delta: MouseScrollDelta) {
+        let (x, y) = match delta {
+            MouseScrollDelta::LineDelta(x, y) => (x, y),
+            MouseScrollDelta::PixelDelta(position) => (
+                position.x as f32 / PIXELS_PER_SCROLL_LINE,
+                position.y as f32 / PIXELS_PER_SCROLL_LINE,
+            ),
+        };
+
+        self.scroll_delta.x += x;
+        self.scroll_delta.y += y;
+    }
+
     pub fn update(&mut self) {
         self.pressed_keys.clear();
         self.released_keys.clear();
+        self.pressed_mouse_buttons.clear();
+        self.released_mouse_buttons.clear();
+        self.scroll_delta = cgmath::Vector2::new(0.0, 0.0);
+        self.pressed_gamepad_buttons.clear();
+        self.released_gamepad_buttons.clear();
+        self.gamepad_connection_events.clear();
+        self.text_input.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_to_string(input_map: &InputMap) -> String {
+        let mut bytes = Vec::new();
+        input_map.write(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn input_map_round_trips() {
+        let mut input_map = InputMap::new();
+        input_map.bind_action("jump", InputSource::Key(VirtualKeyCode::Space));
+        input_map.bind_action("jump", InputSource::GamepadButton(GamepadButton::South));
+        input_map.bind_action("fire", InputSource::MouseButton(MouseButton::Left));
+        input_map.bind_action("menu", InputSource::MouseButton(MouseButton::Other(8)));
+        input_map.bind_axis("move_x", InputSource::Key(VirtualKeyCode::Left), -1.0);
+        input_map.bind_axis(
+            "move_x",
+            InputSource::GamepadAxis(GamepadAxis::LeftStickX),
+            0.5,
+        );
+        input_map.bind_axis2("move", "move_x", "move_y");
+
+        let text = write_to_string(&input_map);
+        assert_eq!(
+            text,
+            "# input map v1\n\
+             action fire mouse:Left\n\
+             action jump key:Space\n\
+             action jump gamepad:South\n\
+             action menu mouse:8\n\
+             axis move_x -1 key:Left\n\
+             axis move_x 0.5 gamepad_axis:LeftStickX\n\
+             axis2 move move_x move_y\n"
+        );
+        assert_eq!(InputMap::read(text.as_bytes()).unwrap(), input_map);
+    }
+
+    #[test]
+    fn backspace_and_delete_are_backspace() {
+        let mut input = Input::new();
+        for c in ['a', '\u{8}', '\u{7f}', '\u{3}', '\r'] {
+            input.character_received(c);
+        }
+
+        assert_eq!(
+            input.text_input(),
+            &[
+                TextInput::Character('a'),
+                TextInput::Backspace,
+                TextInput::Backspace,
+                TextInput::Enter,
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_bindings_are_rejected() {
+        for text in [
+            "action jump\n",
+            "action jump key:NotAKey\n",
+            "action jump keyboard:Space\n",
+            "axis move_x fast key:Left\n",
+            "axis2 move move_x\n",
+        ] {
+            let error = InputMap::read(text.as_bytes()).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", text);
+        }
     }
 }
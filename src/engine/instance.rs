@@ -23,7 +23,7 @@ impl Instance {
 
 impl AsRef<Instance> for Instance {
     fn as_ref(&self) -> &Instance {
-        &self
+        self
     }
 }
 
@@ -1,46 +1,139 @@
+pub mod assets;
+pub mod atlas;
+pub mod buffer;
 pub mod camera;
+mod clock;
+pub mod config;
 pub mod engine_handle;
+pub mod error;
 pub mod game;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+pub mod golden;
+pub mod hot_reload;
 pub mod input;
 pub mod instance;
 pub mod model;
-mod pipeline;
+pub mod pipeline;
+pub mod presenter;
 pub mod render_handle;
-mod state;
+pub mod replay;
+pub mod sprite;
+pub mod state;
 pub mod texture;
 pub mod texture_array;
 pub mod vertex;
+pub mod vfs;
 
 use std::time::Instant;
 use winit::{
-    dpi::LogicalPosition,
+    dpi::{LogicalPosition, LogicalSize},
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    monitor::MonitorHandle,
+    window::{Fullscreen, Icon, Window, WindowBuilder},
 };
 
 pub fn start_game(game: Box<dyn game::Game>) {
-    pollster::block_on(run(game));
+    start_game_with_replay(game, replay::ReplayOptions::default());
+}
+
+// Start the game, optionally recording input to a file or playing it back from one.
+pub fn start_game_with_replay(game: Box<dyn game::Game>, replay_options: replay::ReplayOptions) {
+    pollster::block_on(run(game, replay_options));
+}
+
+// Start the game without a window. The caller drives the returned state's update and
+// render functions, and each frame is rendered into an offscreen texture.
+pub fn start_headless_game(
+    game: Box<dyn game::Game>,
+    width: u32,
+    height: u32,
+    force_fallback_adapter: bool,
+) -> state::State {
+    try_start_headless_game(game, width, height, force_fallback_adapter)
+        .unwrap_or_else(|e| panic!("Failed to start headless game: {}", e))
+}
+
+pub fn try_start_headless_game(
+    game: Box<dyn game::Game>,
+    width: u32,
+    height: u32,
+    force_fallback_adapter: bool,
+) -> Result<state::State, error::EngineError> {
+    let mut state = pollster::block_on(state::State::try_new_headless(
+        width,
+        height,
+        game,
+        force_fallback_adapter,
+    ))?;
+    state.start();
+
+    Ok(state)
 }
 
 // Start the game and handle the update loop.
-async fn run(game: Box<dyn game::Game>) {
+async fn run(game: Box<dyn game::Game>, replay_options: replay::ReplayOptions) {
     env_logger::init();
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
+    let config = game.get_config();
+
+    let mut window_builder = WindowBuilder::new()
         .with_title(game.get_name())
-        .build(&event_loop)
-        .unwrap();
-    window.set_outer_position(get_new_window_position(&window));
+        .with_resizable(config.resizable)
+        .with_fullscreen(get_fullscreen(
+            event_loop.primary_monitor(),
+            config.window_mode,
+        ));
+
+    if let Some((width, height)) = config.window_size {
+        window_builder = window_builder.with_inner_size(LogicalSize::new(width, height));
+    }
+
+    if let Some((width, height)) = config.min_window_size {
+        window_builder = window_builder.with_min_inner_size(LogicalSize::new(width, height));
+    }
+
+    let window = window_builder.build(&event_loop).unwrap();
+
+    if config.window_mode == config::WindowMode::Windowed {
+        window.set_outer_position(get_new_window_position(&window));
+    }
 
-    let fixed_update_rate = game.get_fixed_update_rate();
-    let fixed_update_delta = 1.0 / fixed_update_rate as f32;
+    let mut state = match state::State::try_new(&window, game).await {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to start the engine: {}", e);
+            return;
+        }
+    };
 
-    let mut state = state::State::new(&window, game).await;
+    // The icon is loaded once the engine has set up the VFS.
+    window.set_window_icon(
+        config
+            .icon_res_path
+            .as_deref()
+            .and_then(|res_path| load_icon(state.vfs(), res_path)),
+    );
 
     let mut last_frame_time = Instant::now();
-    let mut fixed_frame_accumulator = 0.0;
-    let mut delta_time = 0.0;
+
+    if let Some(path) = &replay_options.playback_path {
+        let replay = replay::InputReplay::load(path).unwrap_or_else(|e| {
+            panic!("Failed to load input replay from {}: {}", path.display(), e)
+        });
+        state.start_replay(replay);
+    }
+
+    if replay_options.record_path.is_some() {
+        state.start_recording(replay_options.seed);
+    }
+
+    #[cfg(feature = "gamepad")]
+    let mut gamepads = gamepad::GamepadPoller::new();
+
+    #[cfg(feature = "hot_reload")]
+    let res_watcher = hot_reload::ResWatcher::new(state.vfs());
 
     state.start();
 
@@ -53,36 +146,23 @@ async fn run(game: Box<dyn game::Game>) {
 
             match event {
                 WindowEvent::Resized(physical_size) => {
+                    // The platform can also change the window mode, eg. when a macOS window leaves fullscreen.
+                    state.set_fullscreen(window.fullscreen().is_some());
                     state.resize(*physical_size);
                 }
                 WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                     state.resize(**new_inner_size);
                 }
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                    ..
-                } => *control_flow = ControlFlow::Exit,
+                WindowEvent::CloseRequested => state.close_requested(),
                 _ => {}
             }
         }
         Event::RedrawRequested(window_id) if window_id == window.id() => {
             let current_time = Instant::now();
-            delta_time = (current_time - last_frame_time).as_secs_f32();
+            let delta_time = (current_time - last_frame_time).as_secs_f32();
             last_frame_time = current_time;
-            fixed_frame_accumulator += delta_time;
 
-            while fixed_frame_accumulator >= fixed_update_delta {
-                fixed_frame_accumulator -= fixed_update_delta;
-                state.fixed_update();
-            }
-
-            state.update(delta_time);
+            state.advance(delta_time);
             match state.render() {
                 Ok(_) => {}
                 Err(wgpu::SurfaceError::Lost) => state.reconfigure_surface(),
@@ -92,28 +172,122 @@ async fn run(game: Box<dyn game::Game>) {
             }
         }
         Event::MainEventsCleared => {
-            window.request_redraw();
+            #[cfg(feature = "gamepad")]
+            if let Some(gamepads) = &mut gamepads {
+                gamepads.poll(&mut state);
+            }
+
+            #[cfg(feature = "hot_reload")]
+            if let Some(res_watcher) = &res_watcher {
+                for res_path in res_watcher.changed_res_paths() {
+                    state.reload_res(&res_path);
+                }
+            }
+
+            apply_window_requests(&window, &mut state);
+
+            if state.exit_requested() {
+                *control_flow = ControlFlow::Exit;
+            } else {
+                window.request_redraw();
+            }
+        }
+        Event::LoopDestroyed => {
+            state.shutdown();
+
+            if let (Some(path), Some(recorder)) =
+                (&replay_options.record_path, state.stop_recording())
+            {
+                if let Err(e) = recorder.save(path) {
+                    eprintln!(
+                        "Failed to save input recording to {}: {}",
+                        path.display(),
+                        e
+                    );
+                }
+            }
         }
         _ => {}
     });
 }
 
+// Apply window changes that the game requested. Size changes reach the surface through
+// the Resized event that the window sends, since the OS may not resize the window straight away.
+fn apply_window_requests(window: &Window, state: &mut state::State) {
+    let requests = state.take_window_requests();
+
+    if let Some(window_mode) = requests.window_mode {
+        window.set_fullscreen(get_fullscreen(window.current_monitor(), window_mode));
+        state.set_fullscreen(window.fullscreen().is_some());
+    }
+
+    if let Some((width, height)) = requests.size {
+        if window.fullscreen().is_none() {
+            window.set_inner_size(LogicalSize::new(width, height));
+        }
+    }
+
+    if let Some(visible) = requests.cursor_visible {
+        window.set_cursor_visible(visible);
+    }
+
+    if let Some(grab) = requests.cursor_grab {
+        if let Err(e) = window.set_cursor_grab(grab) {
+            eprintln!("Failed to set cursor grab: {}", e);
+        }
+    }
+}
+
+fn get_fullscreen(
+    monitor: Option<MonitorHandle>,
+    window_mode: config::WindowMode,
+) -> Option<Fullscreen> {
+    match window_mode {
+        config::WindowMode::Windowed => None,
+        config::WindowMode::Borderless => Some(Fullscreen::Borderless(None)),
+        config::WindowMode::Exclusive => {
+            let video_mode = monitor.and_then(|monitor| {
+                monitor.video_modes().max_by_key(|mode| {
+                    let size = mode.size();
+                    (size.width * size.height, mode.refresh_rate())
+                })
+            });
+
+            match video_mode {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                _ => Some(Fullscreen::Borderless(None)),
+            }
+        }
+    }
+}
+
+fn load_icon(vfs: &vfs::Vfs, res_path: &str) -> Option<Icon> {
+    let icon = vfs
+        .read_image(res_path)
+        .map_err(|e| e.to_string())
+        .and_then(|img| {
+            let rgba = img.to_rgba8();
+            let (width, height) = rgba.dimensions();
+            Icon::from_rgba(rgba.into_raw(), width, height).map_err(|e| e.to_string())
+        });
+
+    match icon {
+        Ok(icon) => Some(icon),
+        Err(e) => {
+            eprintln!("Failed to load window icon from {}: {}", res_path, e);
+            None
+        }
+    }
+}
+
 fn get_new_window_position(window: &Window) -> LogicalPosition<u32> {
     let monitor_size = window.current_monitor().unwrap().size();
     let window_size = window.inner_size();
     let monitor_center = LogicalPosition::new(monitor_size.width / 2, monitor_size.height / 2);
     let window_center = LogicalPosition::new(window_size.width / 2, window_size.height / 2);
 
-    let center_x = if window_center.x < monitor_center.x {
-        monitor_center.x - window_center.x
-    } else {
-        0
-    };
-    let center_y = if window_center.y < monitor_center.y {
-        monitor_center.y - window_center.y
-    } else {
-        0
-    };
+    let center_x = monitor_center.x.saturating_sub(window_center.x);
+    let center_y = monitor_center.y.saturating_sub(window_center.y);
 
     LogicalPosition::new(center_x, center_y)
 }
@@ -1,42 +1,114 @@
-use crate::engine::vertex::Vertex;
-use wgpu::util::DeviceExt;
+use crate::engine::buffer::DynamicBuffer;
+use crate::engine::vertex::{Vertex, VertexFormat};
 
-pub struct Model {
-    pub vertices: wgpu::Buffer,
-    pub indices: wgpu::Buffer,
-    pub num_indices: u32,
+// A type that can be stored in a Model's index buffer.
+pub trait Index: bytemuck::Pod {
+    const FORMAT: wgpu::IndexFormat;
 }
 
-impl Model {
-    pub fn new(device: &wgpu::Device, vertex_array: &[Vertex], index_array: &[u16]) -> Self {
-        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(vertex_array),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(index_array),
-            usage: wgpu::BufferUsages::INDEX,
-        });
+impl Index for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+}
+
+impl Index for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+}
+
+pub struct Model<V: VertexFormat = Vertex> {
+    vertices: DynamicBuffer<V>,
+    // Stored as bytes since the index format can change when the model is updated.
+    indices: DynamicBuffer<u8>,
+    num_indices: u32,
+    index_format: wgpu::IndexFormat,
+}
+
+impl<V: VertexFormat> Model<V> {
+    // Create a model whose index format matches the index type, eg. u32 for meshes
+    // with more than 65,536 vertices.
+    pub fn new<I: Index>(device: &wgpu::Device, vertex_array: &[V], index_array: &[I]) -> Self {
+        let vertices = DynamicBuffer::new(
+            device,
+            vertex_array,
+            wgpu::BufferUsages::VERTEX,
+            Some("Vertex Buffer"),
+        );
+        let indices = DynamicBuffer::new(
+            device,
+            bytemuck::cast_slice(index_array),
+            wgpu::BufferUsages::INDEX,
+            Some("Index Buffer"),
+        );
         let num_indices = index_array.len() as u32;
 
         Self {
             vertices,
             indices,
             num_indices,
+            index_format: I::FORMAT,
+        }
+    }
+
+    // Create a model with 16 bit indices if every vertex can be indexed with them,
+    // falling back to 32 bit indices for larger meshes.
+    pub fn new_compact(device: &wgpu::Device, vertex_array: &[V], index_array: &[u32]) -> Self {
+        if fits_u16_indices(vertex_array) {
+            Self::new(device, vertex_array, &to_u16_indices(index_array))
+        } else {
+            Self::new(device, vertex_array, index_array)
+        }
+    }
+
+    // Replace the model's mesh, reusing its buffers unless the new mesh is larger.
+    pub fn update<I: Index>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertex_array: &[V],
+        index_array: &[I],
+    ) {
+        self.vertices.update(device, queue, vertex_array);
+        self.indices
+            .update(device, queue, bytemuck::cast_slice(index_array));
+        self.num_indices = index_array.len() as u32;
+        self.index_format = I::FORMAT;
+    }
+
+    // Like update, but uses 16 bit indices when the mesh is small enough for them.
+    pub fn update_compact(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertex_array: &[V],
+        index_array: &[u32],
+    ) {
+        if fits_u16_indices(vertex_array) {
+            self.update(device, queue, vertex_array, &to_u16_indices(index_array));
+        } else {
+            self.update(device, queue, vertex_array, index_array);
         }
     }
 
     pub fn vertices(&self) -> &wgpu::Buffer {
-        &self.vertices
+        self.vertices.buffer()
     }
 
     pub fn indices(&self) -> &wgpu::Buffer {
-        &self.indices
+        self.indices.buffer()
     }
 
     pub fn num_indices(&self) -> u32 {
         self.num_indices
     }
+
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        self.index_format
+    }
+}
+
+fn fits_u16_indices<V>(vertex_array: &[V]) -> bool {
+    vertex_array.len() <= u16::MAX as usize + 1
+}
+
+fn to_u16_indices(index_array: &[u32]) -> Vec<u16> {
+    index_array.iter().map(|&i| i as u16).collect()
 }
@@ -1,67 +1,349 @@
-use crate::engine::{instance, texture, vertex};
+use crate::engine::vertex::VertexFormat;
+use crate::engine::{assets, error, instance, texture, texture_array, vertex, vfs};
 
 pub fn create_pipeline(
     device: &wgpu::Device,
     format: wgpu::TextureFormat,
+    vfs: &vfs::Vfs,
     shader_res_path: &str,
     bind_group_layouts: &[&wgpu::BindGroupLayout],
-) -> wgpu::RenderPipeline {
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Shader"),
-        source: wgpu::ShaderSource::Wgsl(
-            std::fs::read_to_string(format!("res/{}", shader_res_path))
-                .expect(&format!(
-                    "Failed to read shader from file: {}",
-                    shader_res_path
-                ))
-                .into(),
-        ),
-    });
+) -> Result<wgpu::RenderPipeline, error::EngineError> {
+    PipelineBuilder::new(shader_res_path).build(device, format, vfs, bind_group_layouts)
+}
 
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Render Pipeline Layout"),
-        bind_group_layouts,
-        push_constant_ranges: &[],
+pub fn load_shader(
+    device: &wgpu::Device,
+    vfs: &vfs::Vfs,
+    shader_res_path: &str,
+) -> Result<assets::Shader, error::EngineError> {
+    let shader_res_path = resolve_shader_path(device, shader_res_path);
+    let shader_res_path = shader_res_path.as_str();
+
+    let source = vfs
+        .read_to_string(shader_res_path)
+        .map_err(|e| error::EngineError::resource(shader_res_path, e))?;
+
+    // Catch validation errors instead of letting wgpu's default handler panic.
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(shader_res_path),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
     });
 
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[vertex::Vertex::desc(), instance::InstanceRaw::desc()],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(e) => Err(error::EngineError::Shader {
+            res_path: shader_res_path.into(),
+            message: e.to_string(),
         }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            unclipped_depth: false,
-            polygon_mode: wgpu::PolygonMode::Fill,
-            conservative: false,
-        },
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: texture::Texture::DEPTH_FORMAT,
+        None => Ok(assets::Shader::new(shader_res_path, module)),
+    }
+}
+
+// The path of the file that will actually be loaded for the shader on this device.
+pub fn resolve_shader_path(device: &wgpu::Device, shader_res_path: &str) -> String {
+    if texture_array::uses_binding_arrays(device) {
+        shader_res_path.to_string()
+    } else {
+        get_fallback_shader_path(shader_res_path)
+    }
+}
+
+pub fn create_pipeline_with_shader(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    shader: &assets::Shader,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+) -> Result<wgpu::RenderPipeline, error::EngineError> {
+    PipelineBuilder::new(shader.res_path()).build_with_shader(
+        device,
+        format,
+        shader,
+        bind_group_layouts,
+    )
+}
+
+// Common ways of blending a colour target with what's already been drawn.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlendMode {
+    // Overwrite the target.
+    Replace,
+    Alpha,
+    // Alpha blending for colours that were already multiplied by their alpha.
+    Premultiplied,
+    Additive,
+    // Multiply the target by the source colour, eg. for shadows or tinting.
+    Multiply,
+    Custom(wgpu::BlendState),
+}
+
+impl BlendMode {
+    pub fn to_blend_state(self) -> Option<wgpu::BlendState> {
+        let add = |src_factor, dst_factor| wgpu::BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        match self {
+            Self::Replace => None,
+            Self::Alpha => Some(wgpu::BlendState::ALPHA_BLENDING),
+            Self::Premultiplied => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            Self::Additive => Some(wgpu::BlendState {
+                color: add(wgpu::BlendFactor::SrcAlpha, wgpu::BlendFactor::One),
+                alpha: add(wgpu::BlendFactor::One, wgpu::BlendFactor::One),
+            }),
+            Self::Multiply => Some(wgpu::BlendState {
+                color: add(wgpu::BlendFactor::Dst, wgpu::BlendFactor::Zero),
+                alpha: add(wgpu::BlendFactor::DstAlpha, wgpu::BlendFactor::Zero),
+            }),
+            Self::Custom(state) => Some(state),
+        }
+    }
+}
+
+// One of the pipeline's colour outputs. Render passes with several colour attachments,
+// eg. from RenderHandle::begin_custom_pass, need a matching target for each of them.
+#[derive(Copy, Clone, Debug)]
+pub struct ColorTarget {
+    // The surface's format is used when this is None.
+    pub format: Option<wgpu::TextureFormat>,
+    pub blend: BlendMode,
+    pub write_mask: wgpu::ColorWrites,
+}
+
+impl ColorTarget {
+    pub fn new(format: Option<wgpu::TextureFormat>, blend: BlendMode) -> Self {
+        Self {
+            format,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        }
+    }
+}
+
+// Describes a render pipeline, starting from the engine's defaults: "vs_main" and "fs_main"
+// entry points, Vertex and InstanceRaw buffers, a triangle list with back faces culled,
+// one alpha blended target in the surface's format, and depth testing with Less.
+#[derive(Clone, Debug)]
+pub struct PipelineBuilder {
+    shader_res_path: String,
+    vs_entry_point: String,
+    fs_entry_point: String,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    primitive: wgpu::PrimitiveState,
+    color_targets: Vec<ColorTarget>,
+    // Pipelines used with RenderHandle::begin_render_pass need a depth format matching
+    // the camera's depth texture, and ones used with begin_color_pass need None.
+    depth_format: Option<wgpu::TextureFormat>,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+    stencil: wgpu::StencilState,
+    // Has to match the sample count of every attachment in the passes that use the pipeline.
+    sample_count: u32,
+}
+
+impl PipelineBuilder {
+    pub fn new(shader_res_path: &str) -> Self {
+        Self {
+            shader_res_path: shader_res_path.into(),
+            vs_entry_point: "vs_main".into(),
+            fs_entry_point: "fs_main".into(),
+            vertex_layouts: vec![vertex::Vertex::desc(), instance::InstanceRaw::desc()],
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                unclipped_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            color_targets: vec![ColorTarget::new(None, BlendMode::Alpha)],
+            depth_format: Some(texture::Texture::DEPTH_FORMAT),
             depth_write_enabled: true,
             depth_compare: wgpu::CompareFunction::Less,
             stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-    })
+            sample_count: 1,
+        }
+    }
+
+    pub fn shader_res_path(&self) -> &str {
+        &self.shader_res_path
+    }
+
+    pub fn entry_points(mut self, vs_entry_point: &str, fs_entry_point: &str) -> Self {
+        self.vs_entry_point = vs_entry_point.into();
+        self.fs_entry_point = fs_entry_point.into();
+        self
+    }
+
+    // Read vertices of the given format, followed by the usual instance buffer.
+    pub fn vertex_format<V: VertexFormat>(mut self) -> Self {
+        self.vertex_layouts = vec![V::desc(), instance::InstanceRaw::desc()];
+        self
+    }
+
+    pub fn vertex_layouts(mut self, layouts: &[wgpu::VertexBufferLayout<'static>]) -> Self {
+        self.vertex_layouts = layouts.to_vec();
+        self
+    }
+
+    // Eg. LineList for debug drawing.
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.primitive.topology = topology;
+        self
+    }
+
+    // Required when drawing indexed strips, to match the index buffer's format.
+    pub fn strip_index_format(mut self, format: Option<wgpu::IndexFormat>) -> Self {
+        self.primitive.strip_index_format = format;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.primitive.front_face = front_face;
+        self
+    }
+
+    // Set the blending of every colour target.
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        for target in &mut self.color_targets {
+            target.blend = blend;
+        }
+        self
+    }
+
+    // Eg. to render into textures with a different format to the surface, or into
+    // several textures at once.
+    pub fn color_targets(mut self, targets: &[ColorTarget]) -> Self {
+        self.color_targets = targets.to_vec();
+        self
+    }
+
+    pub fn depth(mut self, write_enabled: bool, compare: wgpu::CompareFunction) -> Self {
+        self.depth_write_enabled = write_enabled;
+        self.depth_compare = compare;
+        self
+    }
+
+    // Read depth without writing it, eg. for transparent objects.
+    pub fn depth_read_only(self) -> Self {
+        let compare = self.depth_compare;
+        self.depth(false, compare)
+    }
+
+    // Draw over everything regardless of depth, eg. for UI.
+    pub fn no_depth_test(self) -> Self {
+        self.depth(false, wgpu::CompareFunction::Always)
+    }
+
+    pub fn depth_format(mut self, format: Option<wgpu::TextureFormat>) -> Self {
+        self.depth_format = format;
+        self
+    }
+
+    // Requires a depth format with a stencil aspect.
+    pub fn stencil(mut self, stencil: wgpu::StencilState) -> Self {
+        self.stencil = stencil;
+        self
+    }
+
+    // Eg. 4 for MSAA, which needs multisampled attachments that are resolved at the end
+    // of the pass, see Texture::create_multisampled.
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        vfs: &vfs::Vfs,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> Result<wgpu::RenderPipeline, error::EngineError> {
+        let shader = load_shader(device, vfs, &self.shader_res_path)?;
+        self.build_with_shader(device, format, &shader, bind_group_layouts)
+    }
+
+    // Build using an already loaded shader instead of the builder's shader path.
+    pub fn build_with_shader(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        shader: &assets::Shader,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> Result<wgpu::RenderPipeline, error::EngineError> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let color_targets = self
+            .color_targets
+            .iter()
+            .map(|target| {
+                Some(wgpu::ColorTargetState {
+                    format: target.format.unwrap_or(format),
+                    blend: target.blend.to_blend_state(),
+                    write_mask: target.write_mask,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader.module(),
+                entry_point: &self.vs_entry_point,
+                buffers: &self.vertex_layouts,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader.module(),
+                entry_point: &self.fs_entry_point,
+                targets: &color_targets,
+            }),
+            primitive: self.primitive,
+            depth_stencil: self.depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: self.depth_write_enabled,
+                depth_compare: self.depth_compare,
+                stencil: self.stencil.clone(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(e) => Err(error::EngineError::Shader {
+                res_path: shader.res_path().into(),
+                message: e.to_string(),
+            }),
+            None => Ok(pipeline),
+        }
+    }
+}
+
+// Adapters without binding arrays use a variant of each shader which samples
+// from a texture_2d_array instead, eg. "shader.wgsl" becomes "shader.fallback.wgsl".
+fn get_fallback_shader_path(shader_res_path: &str) -> String {
+    match shader_res_path.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.fallback.{}", stem, extension),
+        None => format!("{}.fallback", shader_res_path),
+    }
 }
@@ -0,0 +1,309 @@
+use crate::engine::vertex::VertexFormat;
+use crate::engine::{
+    assets, buffer, engine_handle, input, pipeline, render_handle, texture, vertex,
+};
+
+// Embedded in the engine, see vfs::ENGINE_FILES.
+const PRESENT_SHADER_RES_PATH: &str = "engine/present.wgsl";
+// Two triangles, drawn without an index buffer.
+const QUAD_VERTEX_COUNT: usize = 6;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScalingMode {
+    // Scale by the largest whole number that fits, so every virtual pixel is the same size.
+    Integer,
+    // Scale as much as possible while keeping the aspect ratio.
+    Fit,
+    // Fill the whole window, ignoring the aspect ratio.
+    Stretch,
+    // Fill the whole window while keeping the aspect ratio, cropping whatever doesn't fit.
+    FillCrop,
+}
+
+// Where the virtual screen ends up in the window, in physical pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PresentLayout {
+    pub offset: cgmath::Vector2<f32>,
+    pub scale: cgmath::Vector2<f32>,
+}
+
+impl PresentLayout {
+    pub fn new(
+        scaling_mode: ScalingMode,
+        virtual_width: u32,
+        virtual_height: u32,
+        window_width: u32,
+        window_height: u32,
+    ) -> Self {
+        let scale_x = window_width as f32 / virtual_width as f32;
+        let scale_y = window_height as f32 / virtual_height as f32;
+
+        let scale = match scaling_mode {
+            ScalingMode::Integer => {
+                let scale = scale_x.min(scale_y).floor().max(1.0);
+                cgmath::Vector2::new(scale, scale)
+            }
+            ScalingMode::Fit => {
+                let scale = scale_x.min(scale_y);
+                cgmath::Vector2::new(scale, scale)
+            }
+            ScalingMode::Stretch => cgmath::Vector2::new(scale_x, scale_y),
+            ScalingMode::FillCrop => {
+                let scale = scale_x.max(scale_y);
+                cgmath::Vector2::new(scale, scale)
+            }
+        };
+
+        // Rounding keeps the virtual pixels lined up with the window's pixels.
+        let offset = cgmath::Vector2::new(
+            ((window_width as f32 - virtual_width as f32 * scale.x) * 0.5).round(),
+            ((window_height as f32 - virtual_height as f32 * scale.y) * 0.5).round(),
+        );
+
+        Self { offset, scale }
+    }
+}
+
+// Draws a fixed-size virtual screen into the window. Games render into the presenter's
+// target, then call present at the end of their render function.
+// Window sizes are in physical pixels, so integer scaling stays pixel-perfect on HiDPI displays.
+pub struct LowResPresenter {
+    pub scaling_mode: ScalingMode,
+    pub letterbox_color: wgpu::Color,
+    virtual_width: u32,
+    virtual_height: u32,
+    window_width: u32,
+    window_height: u32,
+    layout: PresentLayout,
+    // The layout and window size that the quad's vertices were last written for.
+    quad_layout: Option<(PresentLayout, u32, u32)>,
+    target: texture::Texture,
+    pipeline: assets::Handle<wgpu::RenderPipeline>,
+    quad_vertices: buffer::DynamicBuffer<vertex::Vertex>,
+}
+
+impl LowResPresenter {
+    pub fn new(
+        handle: &mut engine_handle::EngineHandle,
+        virtual_width: u32,
+        virtual_height: u32,
+        scaling_mode: ScalingMode,
+    ) -> Self {
+        let target = handle.create_texture(
+            virtual_width,
+            virtual_height,
+            handle.surface_format(),
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            Some("low_res_target"),
+        );
+
+        let builder = pipeline::PipelineBuilder::new(PRESENT_SHADER_RES_PATH)
+            .vertex_layouts(&[vertex::Vertex::desc()])
+            .cull_mode(None)
+            .blend(pipeline::BlendMode::Replace)
+            .depth_format(None);
+        let pipeline =
+            handle.build_pipeline(&builder, &[target.bind_group_layout().unwrap()], None);
+
+        // The vertices are written when presenting, once the window size is known.
+        let quad_vertices = handle.create_dynamic_buffer(
+            &[bytemuck::Zeroable::zeroed(); QUAD_VERTEX_COUNT],
+            wgpu::BufferUsages::VERTEX,
+            Some("Present Quad Buffer"),
+        );
+
+        let (window_width, window_height) = handle.surface_size();
+
+        Self {
+            scaling_mode,
+            letterbox_color: wgpu::Color::BLACK,
+            virtual_width,
+            virtual_height,
+            window_width,
+            window_height,
+            layout: PresentLayout::new(
+                scaling_mode,
+                virtual_width,
+                virtual_height,
+                window_width,
+                window_height,
+            ),
+            quad_layout: None,
+            target,
+            pipeline,
+            quad_vertices,
+        }
+    }
+
+    // Update the layout to match the window, so that coordinate conversions are correct
+    // before the next frame is presented. Presenting always updates the layout too.
+    pub fn update(&mut self, handle: &mut engine_handle::EngineHandle) {
+        let (window_width, window_height) = handle.surface_size();
+        self.update_layout(window_width, window_height);
+    }
+
+    fn update_layout(&mut self, window_width: u32, window_height: u32) {
+        self.window_width = window_width;
+        self.window_height = window_height;
+        self.layout = PresentLayout::new(
+            self.scaling_mode,
+            self.virtual_width,
+            self.virtual_height,
+            window_width,
+            window_height,
+        );
+    }
+
+    fn create_quad_vertices(&self) -> [vertex::Vertex; QUAD_VERTEX_COUNT] {
+        let layout = &self.layout;
+        let window_width = self.window_width as f32;
+        let window_height = self.window_height as f32;
+
+        // Anything outside of clip space gets clipped, which takes care of FillCrop.
+        let left = layout.offset.x / window_width * 2.0 - 1.0;
+        let right = (layout.offset.x + self.virtual_width as f32 * layout.scale.x) / window_width
+            * 2.0
+            - 1.0;
+        let top = 1.0 - layout.offset.y / window_height * 2.0;
+        let bottom = 1.0
+            - (layout.offset.y + self.virtual_height as f32 * layout.scale.y) / window_height * 2.0;
+
+        let corner = |x: f32, y: f32, u: f32, v: f32| vertex::Vertex {
+            position: [x, y, 0.0],
+            tex_coords: [u, v],
+            tex_index: 0,
+            color: [1.0, 1.0, 1.0],
+        };
+
+        let top_left = corner(left, top, 0.0, 0.0);
+        let bottom_right = corner(right, bottom, 1.0, 1.0);
+
+        [
+            top_left,
+            corner(left, bottom, 0.0, 1.0),
+            bottom_right,
+            top_left,
+            bottom_right,
+            corner(right, top, 1.0, 0.0),
+        ]
+    }
+
+    // Draw the virtual screen to the window, surrounded by the letterbox color.
+    pub fn present(&mut self, handle: &mut render_handle::RenderHandle) {
+        let (window_width, window_height) = handle.surface_size();
+        self.update_layout(window_width, window_height);
+
+        let quad_layout = Some((self.layout, window_width, window_height));
+        if self.quad_layout != quad_layout {
+            handle.write_buffer(self.quad_vertices.buffer(), &self.create_quad_vertices());
+            self.quad_layout = quad_layout;
+        }
+
+        let pipeline = handle.get_pipeline(self.pipeline);
+        // The present shader works in clip space, so it doesn't need a camera or depth.
+        let mut render_pass = handle.begin_color_pass(self.letterbox_color, None);
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, self.target.bind_group().unwrap(), &[]);
+        render_pass.set_vertex_buffer(0, self.quad_vertices.slice());
+        render_pass.draw(0..QUAD_VERTEX_COUNT as u32, 0..1);
+    }
+
+    // The texture that games should render the low resolution scene into.
+    pub fn target(&self) -> &texture::Texture {
+        &self.target
+    }
+
+    pub fn virtual_width(&self) -> u32 {
+        self.virtual_width
+    }
+
+    pub fn virtual_height(&self) -> u32 {
+        self.virtual_height
+    }
+
+    pub fn layout(&self) -> PresentLayout {
+        self.layout
+    }
+
+    // Convert a position in the window to the virtual screen's pixels.
+    // Returns None if the position is in the letterbox or was cropped.
+    pub fn window_to_virtual(
+        &self,
+        window_position: cgmath::Vector2<f32>,
+    ) -> Option<cgmath::Vector2<f32>> {
+        let virtual_x = (window_position.x - self.layout.offset.x) / self.layout.scale.x;
+        let virtual_y = (window_position.y - self.layout.offset.y) / self.layout.scale.y;
+
+        if virtual_x < 0.0
+            || virtual_y < 0.0
+            || virtual_x >= self.virtual_width as f32
+            || virtual_y >= self.virtual_height as f32
+        {
+            return None;
+        }
+
+        Some(cgmath::Vector2::new(virtual_x, virtual_y))
+    }
+
+    pub fn virtual_to_window(
+        &self,
+        virtual_position: cgmath::Vector2<f32>,
+    ) -> cgmath::Vector2<f32> {
+        cgmath::Vector2::new(
+            self.layout.offset.x + virtual_position.x * self.layout.scale.x,
+            self.layout.offset.y + virtual_position.y * self.layout.scale.y,
+        )
+    }
+
+    pub fn cursor_position(&self, input: &input::Input) -> Option<cgmath::Vector2<f32>> {
+        self.window_to_virtual(input.cursor_position()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(scaling_mode: ScalingMode, window_width: u32, window_height: u32) -> PresentLayout {
+        PresentLayout::new(scaling_mode, 64, 48, window_width, window_height)
+    }
+
+    fn vector(x: f32, y: f32) -> cgmath::Vector2<f32> {
+        cgmath::Vector2::new(x, y)
+    }
+
+    #[test]
+    fn integer_scaling_uses_whole_scales() {
+        let layout = layout(ScalingMode::Integer, 200, 150);
+        assert_eq!(layout.scale, vector(3.0, 3.0));
+        assert_eq!(layout.offset, vector(4.0, 3.0));
+    }
+
+    #[test]
+    fn integer_scaling_never_shrinks() {
+        let layout = layout(ScalingMode::Integer, 32, 24);
+        assert_eq!(layout.scale, vector(1.0, 1.0));
+        assert_eq!(layout.offset, vector(-16.0, -12.0));
+    }
+
+    #[test]
+    fn fit_keeps_the_aspect_ratio() {
+        let layout = layout(ScalingMode::Fit, 256, 100);
+        assert_eq!(layout.scale, vector(100.0 / 48.0, 100.0 / 48.0));
+        assert_eq!(layout.offset, vector(61.0, 0.0));
+    }
+
+    #[test]
+    fn stretch_fills_the_window() {
+        let layout = layout(ScalingMode::Stretch, 128, 144);
+        assert_eq!(layout.scale, vector(2.0, 3.0));
+        assert_eq!(layout.offset, vector(0.0, 0.0));
+    }
+
+    #[test]
+    fn fill_crop_covers_the_window() {
+        let layout = layout(ScalingMode::FillCrop, 256, 96);
+        assert_eq!(layout.scale, vector(4.0, 4.0));
+        assert_eq!(layout.offset, vector(0.0, -48.0));
+    }
+}
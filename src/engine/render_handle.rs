@@ -1,31 +1,105 @@
-use crate::engine::{camera, texture};
+use crate::engine::{assets, camera, model, texture, vertex};
+use std::ops::Range;
+
+// One colour attachment of a custom render pass.
+pub struct ColorAttachment<'b> {
+    pub view: &'b wgpu::TextureView,
+    // Where a multisampled view is resolved to at the end of the pass.
+    pub resolve_target: Option<&'b wgpu::TextureView>,
+    pub clear_color: wgpu::Color,
+}
 
 // A simple way to access state needed for rendering from a game's render function.
 pub struct RenderHandle<'a> {
     cameras: &'a mut Vec<camera::Camera>,
+    queue: &'a wgpu::Queue,
+    surface_size: (u32, u32),
     view: &'a wgpu::TextureView,
     encoder: &'a mut wgpu::CommandEncoder,
+    alpha: f32,
+    assets: &'a assets::AssetServer,
 }
 
 impl<'a> RenderHandle<'a> {
     pub fn new(
         cameras: &'a mut Vec<camera::Camera>,
+        queue: &'a wgpu::Queue,
+        surface_size: (u32, u32),
         view: &'a wgpu::TextureView,
         encoder: &'a mut wgpu::CommandEncoder,
+        alpha: f32,
+        assets: &'a assets::AssetServer,
     ) -> Self {
         Self {
             cameras,
+            queue,
+            surface_size,
             view,
             encoder,
+            alpha,
+            assets,
         }
     }
 
+    pub fn assets(&self) -> &'a assets::AssetServer {
+        self.assets
+    }
+
+    // Get a pipeline created by EngineHandle::create_pipeline or build_pipeline. This can be
+    // called before beginning a render pass, since the pipeline outlives the pass.
+    pub fn get_pipeline(
+        &self,
+        pipeline: assets::Handle<wgpu::RenderPipeline>,
+    ) -> &'a wgpu::RenderPipeline {
+        self.assets
+            .get(pipeline)
+            .expect("Pipeline was unloaded while still in use")
+    }
+
+    // How far between the last fixed update and the next one this frame is, from 0 to 1.
+    // Games can use this to interpolate between the last two fixed update states.
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    // The size of the window or headless target, in physical pixels.
+    pub fn surface_size(&self) -> (u32, u32) {
+        self.surface_size
+    }
+
+    // Replace the start of a buffer, which must have COPY_DST usage. The write happens
+    // before any of this frame's render passes run.
+    pub fn write_buffer<T: bytemuck::Pod>(&self, buffer: &wgpu::Buffer, contents: &[T]) {
+        self.queue
+            .write_buffer(buffer, 0, bytemuck::cast_slice(contents));
+    }
+
+    // Bind the model's vertices to slot 0 and its indices with the matching index format.
+    pub fn set_model<'b, V: vertex::VertexFormat>(
+        render_pass: &mut wgpu::RenderPass<'b>,
+        model: &'b model::Model<V>,
+    ) {
+        render_pass.set_vertex_buffer(0, model.vertices().slice(..));
+        render_pass.set_index_buffer(model.indices().slice(..), model.index_format());
+    }
+
+    // Bind the model and draw all of its indices. Other vertex buffers, eg. instances,
+    // need to be set beforehand.
+    pub fn draw_model<'b, V: vertex::VertexFormat>(
+        render_pass: &mut wgpu::RenderPass<'b>,
+        model: &'b model::Model<V>,
+        instances: Range<u32>,
+    ) {
+        Self::set_model(render_pass, model);
+        render_pass.draw_indexed(0..model.num_indices(), 0, instances);
+    }
+
     pub fn begin_render_pass<'b>(
         &'b mut self,
         camera_handle: camera::CameraHandle,
         clear_color: wgpu::Color,
         set_target: Option<&'b texture::Texture>,
-    ) -> (wgpu::RenderPass, &camera::Camera) {
+    ) -> (wgpu::RenderPass<'b>, &'b camera::Camera) {
         let view = match set_target {
             Some(t) => t.view(),
             _ => self.view,
@@ -55,4 +129,233 @@ impl<'a> RenderHandle<'a> {
 
         (render_pass, camera)
     }
+
+    // A render pass without a depth attachment, eg. for drawing fullscreen quads or UI.
+    // Pipelines used with it need a depth format of None.
+    pub fn begin_color_pass<'b>(
+        &'b mut self,
+        clear_color: wgpu::Color,
+        set_target: Option<&'b texture::Texture>,
+    ) -> wgpu::RenderPass<'b> {
+        let view = match set_target {
+            Some(t) => t.view(),
+            _ => self.view,
+        };
+
+        self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Color Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        })
+    }
+
+    // The view of the window or headless target, eg. to resolve a multisampled pass into.
+    pub fn surface_view(&self) -> &'a wgpu::TextureView {
+        self.view
+    }
+
+    // A render pass with any number of colour attachments, eg. multisampled ones that
+    // are resolved into the surface, and an optional depth attachment. Pipelines used
+    // with it need a ColorTarget per attachment and a matching sample count.
+    pub fn begin_custom_pass<'b>(
+        &'b mut self,
+        color_attachments: &[ColorAttachment<'b>],
+        depth_view: Option<&'b wgpu::TextureView>,
+    ) -> wgpu::RenderPass<'b> {
+        let color_attachments = color_attachments
+            .iter()
+            .map(|attachment| {
+                Some(wgpu::RenderPassColorAttachment {
+                    view: attachment.view,
+                    resolve_target: attachment.resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(attachment.clear_color),
+                        store: true,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Custom Pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: depth_view.map(|view| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::texture::tests::{assert_all_pixels, create_test_device};
+    use crate::engine::{pipeline, texture};
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    // A triangle covering the whole target, written to two colour outputs.
+    const SHADER: &str = "
+        struct FragmentOutput {
+            @location(0) first: vec4<f32>,
+            @location(1) second: vec4<f32>,
+        }
+
+        @vertex
+        fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+            let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+            return vec4<f32>(uv * 2.0 - 1.0, 0.5, 1.0);
+        }
+
+        @fragment
+        fn fs_first() -> @location(0) vec4<f32> {
+            return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+        }
+
+        @fragment
+        fn fs_both() -> FragmentOutput {
+            return FragmentOutput(vec4<f32>(1.0, 0.0, 0.0, 1.0), vec4<f32>(0.0, 0.0, 1.0, 1.0));
+        }
+    ";
+
+    fn build_pipeline(
+        device: &wgpu::Device,
+        builder: pipeline::PipelineBuilder,
+    ) -> wgpu::RenderPipeline {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        builder
+            .vertex_layouts(&[])
+            .cull_mode(None)
+            .depth_format(None)
+            .build_with_shader(
+                device,
+                FORMAT,
+                &assets::Shader::new("test.wgsl", module),
+                &[],
+            )
+            .unwrap()
+    }
+
+    fn create_target(device: &wgpu::Device) -> texture::Texture {
+        texture::Texture::from_dimensions(
+            device,
+            4,
+            4,
+            FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+            None,
+        )
+        .unwrap()
+    }
+
+    // Record a custom pass that draws the full screen triangle with the pipeline.
+    fn draw_custom_pass(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipeline: &wgpu::RenderPipeline,
+        color_attachments: &[ColorAttachment],
+    ) {
+        let mut cameras = Vec::new();
+        let assets = assets::AssetServer::new();
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut handle = RenderHandle::new(
+                &mut cameras,
+                queue,
+                (4, 4),
+                color_attachments[0].view,
+                &mut encoder,
+                0.0,
+                &assets,
+            );
+            let mut render_pass = handle.begin_custom_pass(color_attachments, None);
+            render_pass.set_pipeline(pipeline);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    #[test]
+    fn multisampled_passes_are_resolved() {
+        let (device, queue) = create_test_device();
+        let pipeline = build_pipeline(
+            &device,
+            pipeline::PipelineBuilder::new("test.wgsl")
+                .entry_points("vs_main", "fs_first")
+                .sample_count(4),
+        );
+        let multisampled =
+            texture::Texture::create_multisampled(&device, 4, 4, FORMAT, 4, None).unwrap();
+        let resolved = create_target(&device);
+
+        draw_custom_pass(
+            &device,
+            &queue,
+            &pipeline,
+            &[ColorAttachment {
+                view: multisampled.view(),
+                resolve_target: Some(resolved.view()),
+                clear_color: wgpu::Color::BLACK,
+            }],
+        );
+
+        let image = resolved.to_image(&device, &queue).unwrap();
+        assert_all_pixels(&image, image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn every_color_target_is_written() {
+        let (device, queue) = create_test_device();
+        let target = pipeline::ColorTarget::new(None, pipeline::BlendMode::Replace);
+        let pipeline = build_pipeline(
+            &device,
+            pipeline::PipelineBuilder::new("test.wgsl")
+                .entry_points("vs_main", "fs_both")
+                .color_targets(&[target, target]),
+        );
+        let first = create_target(&device);
+        let second = create_target(&device);
+
+        draw_custom_pass(
+            &device,
+            &queue,
+            &pipeline,
+            &[
+                ColorAttachment {
+                    view: first.view(),
+                    resolve_target: None,
+                    clear_color: wgpu::Color::BLACK,
+                },
+                ColorAttachment {
+                    view: second.view(),
+                    resolve_target: None,
+                    clear_color: wgpu::Color::BLACK,
+                },
+            ],
+        );
+
+        let first_image = first.to_image(&device, &queue).unwrap();
+        let second_image = second.to_image(&device, &queue).unwrap();
+        assert_all_pixels(&first_image, image::Rgba([255, 0, 0, 255]));
+        assert_all_pixels(&second_image, image::Rgba([0, 0, 255, 255]));
+    }
 }
@@ -0,0 +1,468 @@
+use crate::engine::input::{self, GamepadEvent, InputEvent};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseScrollDelta, VirtualKeyCode};
+
+const REPLAY_HEADER: &str = "# input replay v2";
+// Version 1 replays only held key events, written as "<tick> <Pressed|Released> <key>".
+const REPLAY_HEADER_V1: &str = "# input replay v1";
+
+// Where to record input to and/or play input back from when starting a game.
+#[derive(Default, Clone)]
+pub struct ReplayOptions {
+    pub record_path: Option<PathBuf>,
+    pub playback_path: Option<PathBuf>,
+    // The seed the game was started with, which is saved in recordings so that
+    // they replay in the same world.
+    pub seed: Option<u64>,
+}
+
+impl ReplayOptions {
+    // Pick the seed to run with. When playing back a replay that stored a seed, that
+    // seed is used, and a different requested seed is an error since the replay would desync.
+    pub fn resolve_seed(&self, requested_seed: Option<u64>) -> Result<Option<u64>, io::Error> {
+        let replay_seed = match &self.playback_path {
+            Some(path) => InputReplay::load(path)?.seed(),
+            None => None,
+        };
+
+        match (replay_seed, requested_seed) {
+            (Some(replay_seed), Some(requested_seed)) if replay_seed != requested_seed => {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "The replay was recorded with seed {}, but seed {} was requested",
+                        replay_seed, requested_seed
+                    ),
+                ))
+            }
+            (Some(seed), _) | (None, Some(seed)) => Ok(Some(seed)),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+// An input event, tagged with the number of fixed updates that ran before it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecordedInput {
+    pub tick: u32,
+    pub event: InputEvent,
+}
+
+impl RecordedInput {
+    pub fn key(tick: u32, keycode: VirtualKeyCode, state: ElementState) -> Self {
+        Self {
+            tick,
+            event: InputEvent::Key(keycode, state),
+        }
+    }
+}
+
+// Logs every input event that reaches fixed updates so that a session can be replayed later.
+// Replays are stored as text, starting with a "# input replay v2 seed=<seed>" header
+// followed by one "<tick> <kind> <values...>" line per event, eg. "3 key Pressed Space".
+#[derive(Default)]
+pub struct InputRecorder {
+    seed: Option<u64>,
+    events: Vec<RecordedInput>,
+}
+
+impl InputRecorder {
+    pub fn new(seed: Option<u64>) -> Self {
+        Self {
+            seed,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, tick: u32, event: InputEvent) {
+        self.events.push(RecordedInput { tick, event });
+    }
+
+    pub fn events(&self) -> &[RecordedInput] {
+        &self.events
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file)?;
+
+        file.flush()
+    }
+
+    fn write(&self, mut writer: impl Write) -> Result<(), io::Error> {
+        match self.seed {
+            Some(seed) => writeln!(writer, "{} seed={}", REPLAY_HEADER, seed)?,
+            None => writeln!(writer, "{}", REPLAY_HEADER)?,
+        }
+
+        for event in &self.events {
+            writeln!(writer, "{} {}", event.tick, Self::format_event(event.event))?;
+        }
+
+        Ok(())
+    }
+
+    fn format_event(event: InputEvent) -> String {
+        match event {
+            InputEvent::Key(keycode, state) => format!("key {:?} {:?}", state, keycode),
+            InputEvent::MouseButton(button, state) => {
+                format!("mouse {:?} {}", state, input::mouse_button_name(button))
+            }
+            InputEvent::CursorMoved(x, y) => format!("cursor {} {}", x, y),
+            InputEvent::CursorLeft => "cursor_left".to_string(),
+            InputEvent::MouseWheel(MouseScrollDelta::LineDelta(x, y)) => {
+                format!("scroll_lines {} {}", x, y)
+            }
+            InputEvent::MouseWheel(MouseScrollDelta::PixelDelta(position)) => {
+                format!("scroll_pixels {} {}", position.x, position.y)
+            }
+            // Written as a code point, since the character may be whitespace.
+            InputEvent::Character(c) => format!("char {}", c as u32),
+            InputEvent::Gamepad(GamepadEvent::Connected(id)) => {
+                format!("gamepad_connected {}", id)
+            }
+            InputEvent::Gamepad(GamepadEvent::Disconnected(id)) => {
+                format!("gamepad_disconnected {}", id)
+            }
+            InputEvent::Gamepad(GamepadEvent::ButtonChanged(id, button, state)) => {
+                format!("gamepad_button {} {:?} {:?}", id, state, button)
+            }
+            InputEvent::Gamepad(GamepadEvent::AxisChanged(id, axis, value)) => {
+                format!("gamepad_axis {} {:?} {}", id, axis, value)
+            }
+        }
+    }
+}
+
+// Plays back recorded input events in place of live input.
+pub struct InputReplay {
+    seed: Option<u64>,
+    events: Vec<RecordedInput>,
+    next_event: usize,
+}
+
+impl InputReplay {
+    pub fn new(mut events: Vec<RecordedInput>) -> Self {
+        events.sort_by_key(|e| e.tick);
+
+        Self {
+            seed: None,
+            events,
+            next_event: 0,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        Self::read(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    fn read(reader: impl BufRead) -> Result<Self, io::Error> {
+        let mut seed = None;
+        let mut events = Vec::new();
+
+        for (line_i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            let header_options = line
+                .strip_prefix(REPLAY_HEADER)
+                .or_else(|| line.strip_prefix(REPLAY_HEADER_V1));
+            if let Some(header_options) = header_options {
+                seed = Self::parse_header_seed(header_options).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid replay header: {}", line),
+                    )
+                })?;
+                continue;
+            }
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let event = Self::parse_line(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid replay event on line {}: {}", line_i + 1, line),
+                )
+            })?;
+            events.push(event);
+        }
+
+        Ok(Self {
+            seed,
+            ..Self::new(events)
+        })
+    }
+
+    // Returns Some(None) for a valid header without a seed.
+    fn parse_header_seed(header_options: &str) -> Option<Option<u64>> {
+        match header_options.trim() {
+            "" => Some(None),
+            options => Some(Some(options.strip_prefix("seed=")?.parse().ok()?)),
+        }
+    }
+
+    fn parse_line(line: &str) -> Option<RecordedInput> {
+        let mut parts = line.split_whitespace();
+
+        let tick = parts.next()?.parse().ok()?;
+        let event = match parts.next()? {
+            "key" => {
+                let state = Self::parse_state(parts.next()?)?;
+                InputEvent::Key(input::keycode_from_name(parts.next()?)?, state)
+            }
+            // Version 1 lines have no event kind.
+            state @ ("Pressed" | "Released") => {
+                let state = Self::parse_state(state)?;
+                InputEvent::Key(input::keycode_from_name(parts.next()?)?, state)
+            }
+            "mouse" => {
+                let state = Self::parse_state(parts.next()?)?;
+                InputEvent::MouseButton(input::mouse_button_from_name(parts.next()?)?, state)
+            }
+            "cursor" => {
+                InputEvent::CursorMoved(parts.next()?.parse().ok()?, parts.next()?.parse().ok()?)
+            }
+            "cursor_left" => InputEvent::CursorLeft,
+            "scroll_lines" => InputEvent::MouseWheel(MouseScrollDelta::LineDelta(
+                parts.next()?.parse().ok()?,
+                parts.next()?.parse().ok()?,
+            )),
+            "scroll_pixels" => InputEvent::MouseWheel(MouseScrollDelta::PixelDelta(
+                PhysicalPosition::new(parts.next()?.parse().ok()?, parts.next()?.parse().ok()?),
+            )),
+            "char" => InputEvent::Character(char::from_u32(parts.next()?.parse().ok()?)?),
+            "gamepad_connected" => {
+                InputEvent::Gamepad(GamepadEvent::Connected(parts.next()?.parse().ok()?))
+            }
+            "gamepad_disconnected" => {
+                InputEvent::Gamepad(GamepadEvent::Disconnected(parts.next()?.parse().ok()?))
+            }
+            "gamepad_button" => {
+                let id = parts.next()?.parse().ok()?;
+                let state = Self::parse_state(parts.next()?)?;
+                let button = input::gamepad_button_from_name(parts.next()?)?;
+                InputEvent::Gamepad(GamepadEvent::ButtonChanged(id, button, state))
+            }
+            "gamepad_axis" => {
+                let id = parts.next()?.parse().ok()?;
+                let axis = input::gamepad_axis_from_name(parts.next()?)?;
+                let value = parts.next()?.parse().ok()?;
+                InputEvent::Gamepad(GamepadEvent::AxisChanged(id, axis, value))
+            }
+            _ => return None,
+        };
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(RecordedInput { tick, event })
+    }
+
+    fn parse_state(name: &str) -> Option<ElementState> {
+        match name {
+            "Pressed" => Some(ElementState::Pressed),
+            "Released" => Some(ElementState::Released),
+            _ => None,
+        }
+    }
+
+    // Get the events that should be applied before the given fixed update.
+    pub fn events_for_tick(&mut self, tick: u32) -> &[RecordedInput] {
+        while self.next_event < self.events.len() && self.events[self.next_event].tick < tick {
+            self.next_event += 1;
+        }
+
+        let start = self.next_event;
+        while self.next_event < self.events.len() && self.events[self.next_event].tick == tick {
+            self.next_event += 1;
+        }
+
+        &self.events[start..self.next_event]
+    }
+
+    // The seed that the game was recorded with, if it was saved.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_event >= self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::input::{GamepadAxis, GamepadButton};
+    use winit::event::MouseButton;
+
+    fn write_to_string(recorder: &InputRecorder) -> String {
+        let mut bytes = Vec::new();
+        recorder.write(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn recording_round_trips() {
+        let mut recorder = InputRecorder::new(Some(42));
+        recorder.record(
+            0,
+            InputEvent::Key(VirtualKeyCode::Space, ElementState::Pressed),
+        );
+        recorder.record(
+            3,
+            InputEvent::Key(VirtualKeyCode::Space, ElementState::Released),
+        );
+        recorder.record(
+            3,
+            InputEvent::Key(VirtualKeyCode::Left, ElementState::Pressed),
+        );
+
+        let text = write_to_string(&recorder);
+        assert_eq!(
+            text,
+            "# input replay v2 seed=42\n0 key Pressed Space\n3 key Released Space\n3 key Pressed Left\n"
+        );
+
+        let mut replay = InputReplay::read(text.as_bytes()).unwrap();
+        assert_eq!(replay.seed(), Some(42));
+        assert_eq!(replay.events_for_tick(0).len(), 1);
+        assert!(replay.events_for_tick(1).is_empty());
+        assert_eq!(replay.events_for_tick(3), &recorder.events()[1..]);
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn every_input_event_round_trips() {
+        let events = [
+            InputEvent::MouseButton(MouseButton::Left, ElementState::Pressed),
+            InputEvent::MouseButton(MouseButton::Other(9), ElementState::Released),
+            InputEvent::CursorMoved(12.5, -3.0),
+            InputEvent::CursorLeft,
+            InputEvent::MouseWheel(MouseScrollDelta::LineDelta(0.0, -1.5)),
+            InputEvent::MouseWheel(MouseScrollDelta::PixelDelta(PhysicalPosition::new(
+                4.0, 0.25,
+            ))),
+            InputEvent::Character(' '),
+            InputEvent::Character('\u{8}'),
+            InputEvent::Gamepad(GamepadEvent::Connected(1)),
+            InputEvent::Gamepad(GamepadEvent::ButtonChanged(
+                1,
+                GamepadButton::South,
+                ElementState::Pressed,
+            )),
+            InputEvent::Gamepad(GamepadEvent::AxisChanged(1, GamepadAxis::LeftStickX, -0.75)),
+            InputEvent::Gamepad(GamepadEvent::Disconnected(1)),
+        ];
+
+        let mut recorder = InputRecorder::new(None);
+        for (tick, event) in events.iter().enumerate() {
+            recorder.record(tick as u32, *event);
+        }
+
+        let mut replay = InputReplay::read(write_to_string(&recorder).as_bytes()).unwrap();
+        for (tick, event) in events.iter().enumerate() {
+            assert_eq!(
+                replay.events_for_tick(tick as u32),
+                &[RecordedInput {
+                    tick: tick as u32,
+                    event: *event
+                }]
+            );
+        }
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn version_1_replays_are_read() {
+        let text = "# input replay v1 seed=3\n2 Pressed Space\n";
+        let mut replay = InputReplay::read(text.as_bytes()).unwrap();
+
+        assert_eq!(replay.seed(), Some(3));
+        assert_eq!(
+            replay.events_for_tick(2),
+            &[RecordedInput::key(
+                2,
+                VirtualKeyCode::Space,
+                ElementState::Pressed
+            )]
+        );
+    }
+
+    #[test]
+    fn header_without_seed_is_valid() {
+        let text = write_to_string(&InputRecorder::new(None));
+        assert_eq!(text, "# input replay v2\n");
+        assert_eq!(InputReplay::read(text.as_bytes()).unwrap().seed(), None);
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        for text in [
+            "# input replay v2 seed=abc\n",
+            "0 key Pressed NotAKey\n",
+            "0 key Held Space\n",
+            "0 key Pressed Space extra\n",
+            "0 cursor 1.0\n",
+            "0 char 55296\n",
+            "0 gamepad_axis 0 NotAnAxis 1.0\n",
+            "0 teleport\n",
+        ] {
+            let error = InputReplay::read(text.as_bytes()).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", text);
+        }
+    }
+
+    #[test]
+    fn events_are_sorted_by_tick() {
+        let mut replay = InputReplay::new(vec![
+            RecordedInput::key(5, VirtualKeyCode::A, ElementState::Pressed),
+            RecordedInput::key(1, VirtualKeyCode::B, ElementState::Pressed),
+        ]);
+
+        assert_eq!(
+            replay.events_for_tick(1)[0].event,
+            InputEvent::Key(VirtualKeyCode::B, ElementState::Pressed)
+        );
+        assert_eq!(
+            replay.events_for_tick(5)[0].event,
+            InputEvent::Key(VirtualKeyCode::A, ElementState::Pressed)
+        );
+    }
+
+    #[test]
+    fn resolve_seed_without_replay_uses_requested_seed() {
+        let options = ReplayOptions::default();
+
+        assert_eq!(options.resolve_seed(Some(7)).unwrap(), Some(7));
+        assert_eq!(options.resolve_seed(None).unwrap(), None);
+    }
+
+    #[test]
+    fn resolve_seed_rejects_conflicting_seed() {
+        let path = std::env::temp_dir().join(format!("replay_seed_{}.txt", std::process::id()));
+        InputRecorder::new(Some(42)).save(&path).unwrap();
+
+        let options = ReplayOptions {
+            playback_path: Some(path.clone()),
+            ..Default::default()
+        };
+        let results = [
+            options.resolve_seed(None).map_err(|e| e.kind()),
+            options.resolve_seed(Some(42)).map_err(|e| e.kind()),
+            options.resolve_seed(Some(7)).map_err(|e| e.kind()),
+        ];
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            results,
+            [Ok(Some(42)), Ok(Some(42)), Err(io::ErrorKind::InvalidInput)]
+        );
+    }
+}
@@ -0,0 +1,62 @@
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+}
+
+struct SpriteInput {
+    @location(4) position: vec3<f32>,
+    @location(5) size: vec2<f32>,
+    @location(6) uv_min: vec2<f32>,
+    @location(7) uv_max: vec2<f32>,
+    @location(8) tint: vec4<f32>,
+    @location(9) tex_index: i32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) tex_index: i32,
+    @location(2) tint: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+    sprite: SpriteInput,
+) -> VertexOutput {
+    let position = sprite.position + vec3<f32>(vertex.position.xy * sprite.size, 0.0);
+
+    var out: VertexOutput;
+    out.tex_coords = mix(sprite.uv_min, sprite.uv_max, vertex.tex_coords);
+    out.tex_index = sprite.tex_index;
+    out.tint = sprite.tint;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_diffuse_array: binding_array<texture_2d<f32>>;
+@group(0) @binding(1)
+var s_diffuse_array: binding_array<sampler>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = in.tint * textureSample(
+        t_diffuse_array[in.tex_index],
+        s_diffuse_array[in.tex_index],
+        in.tex_coords
+    );
+
+    // Keep transparent pixels out of the depth buffer, so they don't hide sprites behind them.
+    if (color.a <= 0.0) {
+        discard;
+    }
+
+    return color;
+}
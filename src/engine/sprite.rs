@@ -0,0 +1,216 @@
+use crate::engine::vertex::VertexFormat;
+use crate::engine::{
+    assets, atlas, buffer, camera, engine_handle, model, pipeline, render_handle, texture_array,
+    vertex,
+};
+
+// Embedded in the engine, see vfs::ENGINE_FILES.
+const SPRITE_SHADER_RES_PATH: &str = "engine/sprite.wgsl";
+const QUAD_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
+
+// One sprite to draw, facing the camera's z axis.
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+    // The index of the sprite's texture in the batch's texture array.
+    pub texture_index: u32,
+    // The centre of the sprite.
+    pub position: cgmath::Vector3<f32>,
+    pub size: cgmath::Vector2<f32>,
+    pub tint: [f32; 4],
+    // The part of the texture to draw, eg. a rect from an atlas.
+    pub uv_rect: atlas::UvRect,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // Sprites are drawn in order of layer, then from back to front, ie. from lowest
+    // to highest z, for cameras looking down the z axis.
+    pub layer: i32,
+}
+
+impl Sprite {
+    pub fn new(
+        texture_index: u32,
+        position: cgmath::Vector3<f32>,
+        size: cgmath::Vector2<f32>,
+    ) -> Self {
+        Self {
+            texture_index,
+            position,
+            size,
+            tint: [1.0; 4],
+            uv_rect: atlas::UvRect {
+                min: [0.0, 0.0],
+                max: [1.0, 1.0],
+            },
+            flip_x: false,
+            flip_y: false,
+            layer: 0,
+        }
+    }
+
+    fn to_raw(self) -> SpriteInstance {
+        let mut uv_min = self.uv_rect.min;
+        let mut uv_max = self.uv_rect.max;
+
+        if self.flip_x {
+            std::mem::swap(&mut uv_min[0], &mut uv_max[0]);
+        }
+
+        if self.flip_y {
+            std::mem::swap(&mut uv_min[1], &mut uv_max[1]);
+        }
+
+        SpriteInstance {
+            position: self.position.into(),
+            size: self.size.into(),
+            uv_min,
+            uv_max,
+            tint: self.tint,
+            tex_index: self.texture_index as i32,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteInstance {
+    position: [f32; 3],
+    size: [f32; 2],
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    tint: [f32; 4],
+    tex_index: i32,
+}
+
+impl SpriteInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        4 => Float32x3,
+        5 => Float32x2,
+        6 => Float32x2,
+        7 => Float32x2,
+        8 => Float32x4,
+        9 => Sint32,
+    ];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// Collects sprites that use the same texture array, and draws them all with one
+// instanced draw call. Sprites are drawn each update, then uploaded with prepare, and
+// the uploaded sprites are rendered until the next call to prepare.
+pub struct SpriteBatch {
+    pipeline: assets::Handle<wgpu::RenderPipeline>,
+    quad_model: model::Model,
+    sprites: Vec<Sprite>,
+    instances: Vec<SpriteInstance>,
+    instance_buffer: buffer::DynamicBuffer<SpriteInstance>,
+}
+
+impl SpriteBatch {
+    pub fn new(
+        handle: &mut engine_handle::EngineHandle,
+        texture_array: &texture_array::TextureArray,
+        camera_handle: camera::CameraHandle,
+    ) -> Self {
+        let builder = pipeline::PipelineBuilder::new(SPRITE_SHADER_RES_PATH)
+            .vertex_layouts(&[vertex::Vertex::desc(), SpriteInstance::desc()])
+            .cull_mode(None);
+        let pipeline = handle.build_pipeline(
+            &builder,
+            &[texture_array.bind_group_layout()],
+            Some(camera_handle),
+        );
+
+        let corner = |x: f32, y: f32, u: f32, v: f32| vertex::Vertex {
+            position: [x, y, 0.0],
+            tex_coords: [u, v],
+            tex_index: 0,
+            color: [1.0, 1.0, 1.0],
+        };
+        let vertices = [
+            corner(-0.5, 0.5, 0.0, 0.0),
+            corner(-0.5, -0.5, 0.0, 1.0),
+            corner(0.5, -0.5, 1.0, 1.0),
+            corner(0.5, 0.5, 1.0, 0.0),
+        ];
+        let quad_model = handle.create_model(&vertices, QUAD_INDICES);
+
+        let instance_buffer = handle.create_dynamic_buffer(
+            &[],
+            wgpu::BufferUsages::VERTEX,
+            Some("Sprite Instance Buffer"),
+        );
+
+        Self {
+            pipeline,
+            quad_model,
+            sprites: Vec::new(),
+            instances: Vec::new(),
+            instance_buffer,
+        }
+    }
+
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    // The number of sprites drawn since the last call to prepare.
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    // Sort the sprites drawn since the last call and upload them to be rendered,
+    // then start collecting sprites for the next batch.
+    pub fn prepare(&mut self, handle: &mut engine_handle::EngineHandle) {
+        // Stable sorting keeps sprites at the same layer and depth in the order they were drawn.
+        self.sprites.sort_by(|a, b| {
+            a.layer
+                .cmp(&b.layer)
+                .then(a.position.z.total_cmp(&b.position.z))
+        });
+
+        self.instances.clear();
+        self.instances
+            .extend(self.sprites.drain(..).map(Sprite::to_raw));
+
+        handle.update_dynamic_buffer(&mut self.instance_buffer, &self.instances);
+    }
+
+    // Draw the uploaded sprites. The texture array and camera must be the ones that
+    // the batch was created with, and assets should come from RenderHandle::assets.
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        assets: &'a assets::AssetServer,
+        texture_array: &'a texture_array::TextureArray,
+        camera: &'a camera::Camera,
+    ) {
+        if self.instance_buffer.is_empty() {
+            return;
+        }
+
+        let pipeline = match assets.get(self.pipeline) {
+            Some(pipeline) => pipeline,
+            None => return,
+        };
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, texture_array.bind_group(), &[]);
+        render_pass.set_bind_group(1, camera.bind_group(), &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice());
+        render_handle::RenderHandle::draw_model(
+            render_pass,
+            &self.quad_model,
+            0..self.instance_buffer.len() as u32,
+        );
+    }
+}
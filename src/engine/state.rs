@@ -1,4 +1,7 @@
-use crate::engine::{camera, engine_handle, game, input, render_handle};
+use crate::engine::{
+    assets, camera, clock, config, engine_handle, error, game, hot_reload, input, render_handle,
+    replay, texture, texture_array, vfs,
+};
 
 macro_rules! engine_handle {
     ($sel:ident) => {{
@@ -7,17 +10,40 @@ macro_rules! engine_handle {
             queue,
             config,
             cameras,
+            requests,
+            assets,
+            reload_registry,
+            vfs,
+            target,
+            fullscreen,
             ..
         } = $sel;
 
-        let handle = engine_handle::EngineHandle::new(device, queue, config, cameras);
+        let handle = engine_handle::EngineHandle::new(
+            device,
+            queue,
+            config,
+            cameras,
+            requests,
+            assets,
+            reload_registry,
+            vfs,
+            matches!(target, RenderTarget::Offscreen(_)),
+            *fullscreen,
+        );
         handle
     }};
 }
 
+// Where the final frame ends up, either a window's swapchain or an offscreen texture.
+enum RenderTarget {
+    Surface(wgpu::Surface),
+    Offscreen(texture::Texture),
+}
+
 pub struct State {
     game: Box<dyn game::Game>,
-    surface: wgpu::Surface,
+    target: RenderTarget,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
@@ -25,47 +51,167 @@ pub struct State {
     input_handler: input::Input,
     fixed_input_handler: input::Input,
     cameras: Vec<camera::Camera>,
+    requests: engine_handle::EngineRequests,
+    assets: assets::AssetServer,
+    reload_registry: hot_reload::ReloadRegistry,
+    vfs: vfs::Vfs,
+    fixed_tick: u32,
+    clock: clock::FixedClock,
+    recorder: Option<replay::InputRecorder>,
+    replay: Option<replay::InputReplay>,
+    // Whether the window is fullscreen, as of the last window mode change.
+    fullscreen: bool,
 }
 
 impl State {
     pub async fn new(window: &winit::window::Window, game: Box<dyn game::Game>) -> Self {
+        Self::try_new(window, game)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to create state: {}", e))
+    }
+
+    pub async fn try_new(
+        window: &winit::window::Window,
+        game: Box<dyn game::Game>,
+    ) -> Result<Self, error::EngineError> {
         let size = window.inner_size();
+        let engine_config = game.get_config();
 
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let instance = wgpu::Instance::new(engine_config.backends);
         let surface = unsafe { instance.create_surface(window) };
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference: engine_config.power_preference,
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             })
             .await
-            .expect("Adapter request was not successful!");
+            .ok_or(error::EngineError::NoAdapter)?;
+
+        let (device, queue) = Self::request_device(&adapter).await?;
 
-        let (device, queue) = adapter
+        let supported_formats = surface.get_supported_formats(&adapter);
+        let wants_srgb = engine_config.color_space == config::ColorSpace::Srgb;
+        let format = supported_formats
+            .iter()
+            .copied()
+            .find(|f| f.describe().srgb == wants_srgb)
+            .or_else(|| supported_formats.first().copied())
+            .ok_or(error::EngineError::IncompatibleSurface)?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: engine_config.present_mode,
+        };
+        surface.configure(&device, &config);
+
+        let fullscreen = window.fullscreen().is_some();
+
+        Self::from_parts(
+            game,
+            RenderTarget::Surface(surface),
+            device,
+            queue,
+            config,
+            fullscreen,
+        )
+    }
+
+    // Create a state that renders into an offscreen texture instead of a window.
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        game: Box<dyn game::Game>,
+        force_fallback_adapter: bool,
+    ) -> Self {
+        Self::try_new_headless(width, height, game, force_fallback_adapter)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to create headless state: {}", e))
+    }
+
+    pub async fn try_new_headless(
+        width: u32,
+        height: u32,
+        game: Box<dyn game::Game>,
+        force_fallback_adapter: bool,
+    ) -> Result<Self, error::EngineError> {
+        let engine_config = game.get_config();
+
+        let instance = wgpu::Instance::new(engine_config.backends);
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: engine_config.power_preference,
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await
+            .ok_or(error::EngineError::NoAdapter)?;
+
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        let format = match engine_config.color_space {
+            config::ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            config::ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        };
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width,
+            height,
+            present_mode: engine_config.present_mode,
+        };
+        let target = RenderTarget::Offscreen(Self::create_offscreen_texture(&device, &config));
+
+        Self::from_parts(game, target, device, queue, config, false)
+    }
+
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), error::EngineError> {
+        // Binding arrays are optional, texture arrays fall back to layered textures without them.
+        let features = texture_array::BINDING_ARRAY_FEATURES & adapter.features();
+
+        let device_and_queue = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::TEXTURE_BINDING_ARRAY | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+                    features,
                     limits: wgpu::Limits::default(),
                     label: None,
                 },
                 None,
             )
-            .await
-            .expect("Device request was not successful!");
+            .await?;
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_supported_formats(&adapter)[0],
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::AutoVsync,
-        };
-        surface.configure(&device, &config);
+        Ok(device_and_queue)
+    }
 
-        Self {
+    fn from_parts(
+        game: Box<dyn game::Game>,
+        target: RenderTarget,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        fullscreen: bool,
+    ) -> Result<Self, error::EngineError> {
+        let engine_config = game.get_config();
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+        let clock = clock::FixedClock::new(
+            game.get_fixed_update_rate(),
+            engine_config.max_fixed_updates_per_frame,
+        );
+        let mut vfs = vfs::Vfs::from_sources(&engine_config.mounts)?;
+        vfs.mount(
+            &vfs::MountSource::Embedded(vfs::ENGINE_FILES),
+            vfs::ENGINE_FILES_PRIORITY,
+        )?;
+
+        Ok(Self {
             game,
-            surface,
+            target,
             device,
             queue,
             config,
@@ -73,13 +219,99 @@ impl State {
             input_handler: input::Input::new(),
             fixed_input_handler: input::Input::new(),
             cameras: Vec::new(),
-        }
+            requests: engine_handle::EngineRequests::default(),
+            assets: assets::AssetServer::new(),
+            reload_registry: hot_reload::ReloadRegistry::new(),
+            vfs,
+            fixed_tick: 0,
+            clock,
+            recorder: None,
+            replay: None,
+            fullscreen,
+        })
+    }
+
+    fn create_offscreen_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> texture::Texture {
+        texture::Texture::from_dimensions(
+            device,
+            config.width,
+            config.height,
+            config.format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            Some("offscreen_texture"),
+        )
+        .expect("Failed to create offscreen texture")
     }
 
     pub fn start(&mut self) {
         let mut handle = engine_handle!(self);
 
         self.game.start(&mut handle);
+        self.apply_requests();
+    }
+
+    pub fn close_requested(&mut self) {
+        let mut handle = engine_handle!(self);
+
+        self.game.on_close_requested(&mut handle);
+        self.apply_requests();
+    }
+
+    pub fn exit_requested(&self) -> bool {
+        self.requests.exit
+    }
+
+    pub fn take_window_requests(&mut self) -> engine_handle::WindowRequests {
+        std::mem::take(&mut self.requests.window)
+    }
+
+    // Called by the event loop after it changes the window mode.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+    }
+
+    pub fn shutdown(&mut self) {
+        let mut handle = engine_handle!(self);
+
+        self.game.shutdown(&mut handle);
+    }
+
+    pub fn vfs(&self) -> &vfs::Vfs {
+        &self.vfs
+    }
+
+    // Update the textures, shaders and pipelines that were loaded from the file.
+    pub fn reload_res(&mut self, res_path: &str) {
+        self.reload_registry.reload(
+            &self.device,
+            &self.queue,
+            self.config.format,
+            &self.vfs,
+            &mut self.assets,
+            res_path,
+        );
+    }
+
+    fn apply_requests(&mut self) {
+        if let Some(input_map) = self.requests.input_map.take() {
+            self.input_handler.set_input_map(input_map.clone());
+            self.fixed_input_handler.set_input_map(input_map);
+        }
+
+        if let Some(time_scale) = self.requests.time_scale.take() {
+            self.clock.set_time_scale(time_scale);
+        }
+
+        if let Some(paused) = self.requests.paused.take() {
+            self.clock.set_paused(paused);
+        }
+
+        for _ in 0..std::mem::take(&mut self.requests.fixed_steps) {
+            self.clock.step();
+        }
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -92,7 +324,12 @@ impl State {
                 camera.resize(&self.device, self.config.width, self.config.height);
             }
 
-            self.surface.configure(&self.device, &self.config);
+            match &mut self.target {
+                RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+                RenderTarget::Offscreen(texture) => {
+                    *texture = Self::create_offscreen_texture(&self.device, &self.config)
+                }
+            }
         }
     }
 
@@ -101,8 +338,10 @@ impl State {
     }
 
     pub fn input(&mut self, event: &winit::event::WindowEvent) {
-        match event {
-            winit::event::WindowEvent::KeyboardInput {
+        use winit::event::WindowEvent;
+
+        let event = match event {
+            WindowEvent::KeyboardInput {
                 input:
                     winit::event::KeyboardInput {
                         state,
@@ -110,20 +349,88 @@ impl State {
                         ..
                     },
                 ..
-            } => {
-                self.input_handler.key_state_changed(*keycode, *state);
-                self.fixed_input_handler.key_state_changed(*keycode, *state);
+            } => input::InputEvent::Key(*keycode, *state),
+            WindowEvent::ReceivedCharacter(c) => input::InputEvent::Character(*c),
+            WindowEvent::MouseInput { state, button, .. } => {
+                input::InputEvent::MouseButton(*button, *state)
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                input::InputEvent::CursorMoved(position.x as f32, position.y as f32)
             }
-            _ => {}
+            WindowEvent::CursorLeft { .. } => input::InputEvent::CursorLeft,
+            WindowEvent::MouseWheel { delta, .. } => input::InputEvent::MouseWheel(*delta),
+            _ => return,
+        };
+
+        self.input_event(event);
+    }
+
+    // Every input goes through here, so that recordings see everything fixed updates see.
+    // Fixed updates only see recorded input while a replay is playing.
+    pub fn input_event(&mut self, event: input::InputEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.fixed_tick, event);
         }
+
+        self.input_handler.handle_event(event);
+
+        if self.replay.is_none() {
+            self.fixed_input_handler.handle_event(event);
+        }
+    }
+
+    pub fn key_state_changed(
+        &mut self,
+        keycode: winit::event::VirtualKeyCode,
+        state: winit::event::ElementState,
+    ) {
+        self.input_event(input::InputEvent::Key(keycode, state));
+    }
+
+    pub fn gamepad_event(&mut self, event: input::GamepadEvent) {
+        self.input_event(input::InputEvent::Gamepad(event));
+    }
+
+    // The seed is saved with the recording, so that it can be replayed in the same world.
+    pub fn start_recording(&mut self, seed: Option<u64>) {
+        self.recorder = Some(replay::InputRecorder::new(seed));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<replay::InputRecorder> {
+        self.recorder.take()
+    }
+
+    pub fn start_replay(&mut self, replay: replay::InputReplay) {
+        self.replay = Some(replay);
+    }
+
+    pub fn fixed_update_rate(&self) -> u32 {
+        self.game.get_fixed_update_rate()
+    }
+
+    // Run however many fixed updates have built up since the last frame, followed by an update.
+    pub fn advance(&mut self, delta_time: f32) {
+        for _ in 0..self.clock.advance(delta_time) {
+            self.fixed_update();
+        }
+
+        self.update(self.clock.scale_delta(delta_time));
     }
 
     pub fn fixed_update(&mut self) {
+        if let Some(replay) = &mut self.replay {
+            for event in replay.events_for_tick(self.fixed_tick) {
+                self.fixed_input_handler.handle_event(event.event);
+            }
+        }
+
         let mut handle = engine_handle!(self);
 
         self.game
             .fixed_update(&self.fixed_input_handler, &mut handle);
+        self.apply_requests();
         self.fixed_input_handler.update();
+        self.fixed_tick = self.fixed_tick.wrapping_add(1);
     }
 
     pub fn update(&mut self, delta_time: f32) {
@@ -131,6 +438,7 @@ impl State {
 
         self.game
             .update(&self.input_handler, &mut handle, delta_time);
+        self.apply_requests();
 
         let State { cameras, .. } = self;
 
@@ -142,10 +450,16 @@ impl State {
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (output, view) = match &self.target {
+            RenderTarget::Surface(surface) => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output), view)
+            }
+            RenderTarget::Offscreen(texture) => (None, texture.create_view()),
+        };
 
         let mut encoder = self
             .device
@@ -153,14 +467,41 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
-        let mut render_handle =
-            render_handle::RenderHandle::new(&mut self.cameras, &view, &mut encoder);
+        let mut render_handle = render_handle::RenderHandle::new(
+            &mut self.cameras,
+            &self.queue,
+            (self.config.width, self.config.height),
+            &view,
+            &mut encoder,
+            self.clock.alpha(),
+            &self.assets,
+        );
 
         self.game.render(&mut render_handle);
 
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+
+        if let Some(output) = output {
+            output.present();
+        }
 
         Ok(())
     }
+
+    // The texture a headless state renders into, or None when rendering to a window.
+    pub fn offscreen_texture(&self) -> Option<&texture::Texture> {
+        match &self.target {
+            RenderTarget::Offscreen(texture) => Some(texture),
+            RenderTarget::Surface(_) => None,
+        }
+    }
+
+    // Read back the last frame rendered by a headless state.
+    pub fn capture_frame(&self) -> Option<image::RgbaImage> {
+        self.offscreen_texture().map(|texture| {
+            texture
+                .to_image(&self.device, &self.queue)
+                .unwrap_or_else(|e| panic!("Failed to capture frame: {}", e))
+        })
+    }
 }
@@ -1,12 +1,18 @@
+use crate::engine::{error, vfs};
 use image::GenericImageView;
 use std::io;
+use std::sync::Arc;
 
 pub struct Texture {
-    texture: wgpu::Texture,
+    // Shared so that hot reloading can write into textures that the game owns.
+    texture: Arc<wgpu::Texture>,
     view: wgpu::TextureView,
     sampler: wgpu::Sampler,
     bind_group_layout: Option<wgpu::BindGroupLayout>,
     bind_group: Option<wgpu::BindGroup>,
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    res_path: Option<String>,
 }
 
 impl Texture {
@@ -49,22 +55,83 @@ impl Texture {
         });
 
         Self {
-            texture,
+            texture: Arc::new(texture),
             view,
             sampler,
             bind_group_layout: None,
             bind_group: None,
+            size,
+            format: Self::DEPTH_FORMAT,
+            res_path: None,
+        }
+    }
+
+    // A texture that can only be rendered into, eg. a multisampled colour or depth
+    // attachment for MSAA. Multisampled colour attachments need a resolve target
+    // with one sample to be read afterwards.
+    pub fn create_multisampled(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: Option<&str>,
+    ) -> Result<Self, error::EngineError> {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        // Not every sample count is supported, so catch the validation error.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        if let Some(e) = pollster::block_on(device.pop_error_scope()) {
+            return Err(error::EngineError::Texture {
+                label: label.map(|l| l.into()),
+                message: e.to_string(),
+            });
         }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Ok(Self {
+            texture: Arc::new(texture),
+            view,
+            sampler,
+            bind_group_layout: None,
+            bind_group: None,
+            size,
+            format,
+            res_path: None,
+        })
     }
 
     pub fn from_path(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        vfs: &vfs::Vfs,
         res_path: &str,
-    ) -> Result<Self, io::Error> {
-        let full_path = format!("res/{}", res_path);
-        let bytes = std::fs::read(full_path)?;
-        Self::from_bytes(&device, &queue, &bytes, res_path)
+    ) -> Result<Self, error::EngineError> {
+        let bytes = vfs
+            .read(res_path)
+            .map_err(|e| error::EngineError::resource(res_path, e))?;
+        let mut texture = Self::from_bytes(device, queue, &bytes, res_path)
+            .map_err(|e| error::EngineError::resource(res_path, e))?;
+        texture.res_path = Some(res_path.into());
+
+        Ok(texture)
     }
 
     pub fn from_bytes(
@@ -72,8 +139,8 @@ impl Texture {
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: &str,
-    ) -> Result<Self, io::Error> {
-        let img = image::load_from_memory(bytes).expect("Couldn't load image from bytes");
+    ) -> Result<Self, error::EngineError> {
+        let img = image::load_from_memory(bytes)?;
         Self::from_image(device, queue, &img, Some(label))
     }
 
@@ -82,7 +149,7 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
-    ) -> Result<Self, io::Error> {
+    ) -> Result<Self, error::EngineError> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
@@ -104,7 +171,7 @@ impl Texture {
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 aspect: wgpu::TextureAspect::All,
-                texture: &new_texture.texture,
+                texture: new_texture.texture(),
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
@@ -127,12 +194,17 @@ impl Texture {
         format: wgpu::TextureFormat,
         extra_usages: wgpu::TextureUsages,
         label: Option<&str>,
-    ) -> Result<Self, io::Error> {
+    ) -> Result<Self, error::EngineError> {
         let size = wgpu::Extent3d {
             width,
             height,
             depth_or_array_layers: 1,
         };
+
+        // Catch validation errors, eg. from oversized textures, instead of letting
+        // wgpu's default handler panic.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
@@ -142,9 +214,17 @@ impl Texture {
             format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | extra_usages,
         });
 
+        if let Some(e) = pollster::block_on(device.pop_error_scope()) {
+            return Err(error::EngineError::Texture {
+                label: label.map(|l| l.into()),
+                message: e.to_string(),
+            });
+        }
+
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -156,28 +236,10 @@ impl Texture {
             ..Default::default()
         });
 
-        let bind_group_layout: wgpu::BindGroupLayout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
+        // Plain textures are bound as a texture_2d with or without binding arrays, only
+        // texture arrays fall back to a texture_2d_array.
+        let bind_group_layout =
+            Self::create_bind_group_layout(device, wgpu::TextureViewDimension::D2);
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
             entries: &[
@@ -194,14 +256,163 @@ impl Texture {
         });
 
         Ok(Self {
-            texture,
+            texture: Arc::new(texture),
             view,
             sampler,
             bind_group_layout: Some(bind_group_layout),
             bind_group: Some(bind_group),
+            size,
+            format,
+            res_path: None,
         })
     }
 
+    // Create another view like the one returned by view, eg. to render into.
+    pub fn create_view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub fn create_layered_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        Self::create_bind_group_layout(device, wgpu::TextureViewDimension::D2Array)
+    }
+
+    fn create_bind_group_layout(
+        device: &wgpu::Device,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        })
+    }
+
+    // Copy the texture's contents back from the GPU. The texture must have been
+    // created with COPY_SRC usage and use an 8-bit RGBA or BGRA format.
+    pub fn to_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<image::RgbaImage, error::EngineError> {
+        let is_bgra = match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => return Err(error::EngineError::UnsupportedReadbackFormat(self.format)),
+        };
+
+        let width = self.size.width;
+        let height = self.size.height;
+
+        // Rows copied into a buffer have to be padded to a multiple of COPY_BYTES_PER_ROW_ALIGNMENT.
+        let unpadded_bytes_per_row = 4 * width;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Texture Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: self.texture(),
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+            },
+            self.size,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let buffer_slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+
+        receiver.recv().map_err(io::Error::other)??;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let padded_data = buffer_slice.get_mapped_range();
+            for row in padded_data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        if is_bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Texture readback returned the wrong amount of data",
+            )
+            .into()
+        })
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn shared_texture(&self) -> &Arc<wgpu::Texture> {
+        &self.texture
+    }
+
+    // The path in res/ that the texture was loaded from, if any.
+    pub fn res_path(&self) -> Option<&str> {
+        self.res_path.as_deref()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.size.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.size.height
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
     pub fn view(&self) -> &wgpu::TextureView {
         &self.view
     }
@@ -224,3 +435,185 @@ impl Texture {
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::engine::vertex::VertexFormat;
+    use crate::engine::{assets, pipeline, vertex};
+    use wgpu::util::DeviceExt;
+
+    // A device on the fallback adapter, for tests that need the GPU.
+    pub fn create_test_device() -> (wgpu::Device, wgpu::Queue) {
+        pollster::block_on(async {
+            let instance = wgpu::Instance::new(wgpu::Backends::all());
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    force_fallback_adapter: true,
+                    ..Default::default()
+                })
+                .await
+                .expect("Failed to find a fallback adapter");
+
+            adapter
+                .request_device(&wgpu::DeviceDescriptor::default(), None)
+                .await
+                .expect("Failed to request a device")
+        })
+    }
+
+    #[test]
+    fn oversized_textures_are_errors() {
+        let (device, _) = create_test_device();
+        let width = device.limits().max_texture_dimension_2d + 1;
+
+        let result = Texture::from_dimensions(
+            &device,
+            width,
+            1,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::empty(),
+            Some("oversized"),
+        );
+
+        assert!(matches!(
+            result,
+            Err(error::EngineError::Texture { label: Some(label), .. }) if label == "oversized"
+        ));
+    }
+
+    #[test]
+    fn only_8_bit_rgba_textures_are_read_back() {
+        let (device, queue) = create_test_device();
+        let texture = Texture::from_dimensions(
+            &device,
+            4,
+            4,
+            wgpu::TextureFormat::R8Unorm,
+            wgpu::TextureUsages::empty(),
+            None,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            texture.to_image(&device, &queue),
+            Err(error::EngineError::UnsupportedReadbackFormat(
+                wgpu::TextureFormat::R8Unorm
+            ))
+        ));
+    }
+
+    // Samples a plain texture_2d, the same as the presenter.
+    pub const SAMPLE_SHADER: &str = include_str!("res/present.wgsl");
+
+    // Draw a texture over a 4x4 target with a shader like SAMPLE_SHADER, whose vertices are
+    // in clip space, and read the result back.
+    pub fn render_sampled(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader_source: &str,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        bind_group: &wgpu::BindGroup,
+    ) -> image::RgbaImage {
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let target = Texture::from_dimensions(
+            device,
+            4,
+            4,
+            format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+            None,
+        )
+        .unwrap();
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+        });
+        let pipeline = pipeline::PipelineBuilder::new("sample.wgsl")
+            .vertex_layouts(&[vertex::Vertex::desc()])
+            .cull_mode(None)
+            .depth_format(None)
+            .build_with_shader(
+                device,
+                format,
+                &assets::Shader::new("sample.wgsl", module),
+                &[bind_group_layout],
+            )
+            .unwrap();
+
+        // One triangle covering the whole target.
+        let corner = |x: f32, y: f32| vertex::Vertex {
+            position: [x, y, 0.0],
+            tex_coords: [(x + 1.0) * 0.5, (1.0 - y) * 0.5],
+            tex_index: 0,
+            color: [1.0, 1.0, 1.0],
+        };
+        let vertices = [corner(-1.0, -1.0), corner(3.0, -1.0), corner(-1.0, 3.0)];
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target.view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        target.to_image(device, queue).unwrap()
+    }
+
+    pub fn assert_all_pixels(image: &image::RgbaImage, color: image::Rgba<u8>) {
+        for pixel in image.pixels() {
+            assert!(
+                pixel.0.iter().zip(color.0).all(|(a, e)| a.abs_diff(e) <= 1),
+                "Expected {:?}, found {:?}",
+                color,
+                pixel
+            );
+        }
+    }
+
+    pub fn solid_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: image::Rgba<u8>,
+    ) -> Texture {
+        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, color));
+        Texture::from_image(device, queue, &image, None).unwrap()
+    }
+
+    #[test]
+    fn sampled_textures_have_their_contents() {
+        let (device, queue) = create_test_device();
+        let color = image::Rgba([200, 40, 90, 255]);
+        let texture = solid_texture(&device, &queue, color);
+
+        let image = render_sampled(
+            &device,
+            &queue,
+            SAMPLE_SHADER,
+            texture.bind_group_layout().unwrap(),
+            texture.bind_group().unwrap(),
+        );
+        assert_all_pixels(&image, color);
+    }
+}
@@ -1,17 +1,52 @@
-use crate::engine::texture;
+use crate::engine::{error, texture};
 use std::num::NonZeroU32;
+use std::sync::Arc;
+
+// Features needed to bind an array of separate textures. Without them, textures are
+// copied into the layers of a single texture_2d_array instead.
+pub const BINDING_ARRAY_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+    .union(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+
+// Layered textures always have at least this many layers, since some backends, eg. GL,
+// treat a texture with a single layer as a plain 2D texture and sample it as black
+// when it's bound as a texture_2d_array.
+pub const MIN_LAYERS: u32 = 2;
+
+pub fn uses_binding_arrays(device: &wgpu::Device) -> bool {
+    device.features().contains(BINDING_ARRAY_FEATURES)
+}
 
 pub struct TextureArray {
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
+    // The textures bound individually when using binding arrays, which are kept
+    // so that they can be updated in place.
+    textures: Vec<texture::Texture>,
+    // The texture holding a copy of each texture in its layers otherwise.
+    layered_texture: Option<Arc<wgpu::Texture>>,
 }
 
 impl TextureArray {
-    pub fn new(device: &wgpu::Device, textures: Vec<texture::Texture>) -> Result<Self, String> {
-        let texture_count = textures.len();
-        if texture_count < 1 {
-            return Err("Attempted to create a texture array without any textures!".to_string());
+    // Without binding arrays every texture has to share the same size and format, so
+    // textures of different sizes should be packed with atlas::AtlasBuilder instead.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: Vec<texture::Texture>,
+    ) -> Result<Self, error::EngineError> {
+        if textures.is_empty() {
+            return Err(error::EngineError::EmptyTextureArray);
+        }
+
+        if uses_binding_arrays(device) {
+            Ok(Self::new_binding_array(device, textures))
+        } else {
+            Self::new_layered(device, queue, textures)
         }
+    }
+
+    fn new_binding_array(device: &wgpu::Device, textures: Vec<texture::Texture>) -> Self {
+        let texture_count = textures.len();
 
         let bind_group_layout: wgpu::BindGroupLayout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -38,9 +73,9 @@ impl TextureArray {
 
         let mut texture_views = Vec::new();
         let mut texture_samplers = Vec::new();
-        for i in 0..textures.len() {
-            texture_views.push(textures[i].view());
-            texture_samplers.push(textures[i].sampler());
+        for texture in &textures {
+            texture_views.push(texture.view());
+            texture_samplers.push(texture.sampler());
         }
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -58,9 +93,98 @@ impl TextureArray {
             label: Some("Texture array"),
         });
 
+        Self {
+            bind_group_layout,
+            bind_group,
+            textures,
+            layered_texture: None,
+        }
+    }
+
+    // Copy every texture into one layer of a texture_2d_array, which requires
+    // them all to have the same size and format.
+    fn new_layered(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: Vec<texture::Texture>,
+    ) -> Result<Self, error::EngineError> {
+        let first = &textures[0];
+        let (width, height, format) = (first.width(), first.height(), first.format());
+
+        if textures
+            .iter()
+            .any(|t| t.width() != width || t.height() != height || t.format() != format)
+        {
+            return Err(error::EngineError::MismatchedTextureArray);
+        }
+
+        let layer_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let array_texture = Arc::new(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture array"),
+            size: wgpu::Extent3d {
+                depth_or_array_layers: (textures.len() as u32).max(MIN_LAYERS),
+                ..layer_size
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        }));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Texture Array Encoder"),
+        });
+
+        for (i, texture) in textures.iter().enumerate() {
+            encoder.copy_texture_to_texture(
+                texture.texture().as_image_copy(),
+                wgpu::ImageCopyTexture {
+                    texture: &array_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: i as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                layer_size,
+            );
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let view = array_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let bind_group_layout = texture::Texture::create_layered_bind_group_layout(device);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(first.sampler()),
+                },
+            ],
+            label: Some("Texture array"),
+        });
+
         Ok(Self {
             bind_group_layout,
             bind_group,
+            textures: Vec::new(),
+            layered_texture: Some(array_texture),
         })
     }
 
@@ -71,4 +195,65 @@ impl TextureArray {
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
     }
+
+    pub fn textures(&self) -> &[texture::Texture] {
+        &self.textures
+    }
+
+    pub fn layered_texture(&self) -> Option<&Arc<wgpu::Texture>> {
+        self.layered_texture.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::texture::tests::{
+        assert_all_pixels, create_test_device, render_sampled, solid_texture, SAMPLE_SHADER,
+    };
+
+    // Samples layer 0 of a texture_2d_array, like the fallback shaders.
+    fn layered_sample_shader() -> String {
+        SAMPLE_SHADER
+            .replace("texture_2d<f32>", "texture_2d_array<f32>")
+            .replace("in.tex_coords)", "in.tex_coords, 0)")
+    }
+
+    #[test]
+    fn single_texture_arrays_have_their_contents() {
+        let (device, queue) = create_test_device();
+        let color = image::Rgba([30, 160, 220, 255]);
+        let texture = solid_texture(&device, &queue, color);
+        let texture_array = TextureArray::new(&device, &queue, vec![texture]).unwrap();
+
+        // The fallback adapter doesn't support binding arrays, so this covers the layered path.
+        let image = render_sampled(
+            &device,
+            &queue,
+            &layered_sample_shader(),
+            texture_array.bind_group_layout(),
+            texture_array.bind_group(),
+        );
+        assert_all_pixels(&image, color);
+    }
+
+    #[test]
+    fn mismatched_layers_are_rejected() {
+        let (device, queue) = create_test_device();
+        let small = solid_texture(&device, &queue, image::Rgba([0; 4]));
+        let large = texture::Texture::from_dimensions(
+            &device,
+            4,
+            4,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::empty(),
+            None,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            TextureArray::new(&device, &queue, vec![small, large]),
+            Err(error::EngineError::MismatchedTextureArray)
+        ));
+    }
 }
@@ -1,3 +1,9 @@
+// A type that can be stored in a Model's vertex buffer, along with the layout that
+// pipelines use to read it.
+pub trait VertexFormat: bytemuck::Pod {
+    fn desc() -> wgpu::VertexBufferLayout<'static>;
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -7,8 +13,8 @@ pub struct Vertex {
     pub color: [f32; 3],
 }
 
-impl Vertex {
-    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+impl VertexFormat for Vertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
 
         wgpu::VertexBufferLayout {
@@ -0,0 +1,349 @@
+use crate::engine::error;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+// Files compiled into the executable, as pairs of res paths and contents. See embed_res!.
+pub type EmbeddedFiles = &'static [(&'static str, &'static [u8])];
+
+// Build EmbeddedFiles from paths in the calling crate's res/ directory, eg.
+// embed_res!["shader.wgsl", "player.png"].
+#[macro_export]
+macro_rules! embed_res {
+    ($($res_path:literal),* $(,)?) => {
+        &[$((
+            $res_path,
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/res/", $res_path)) as &[u8],
+        )),*]
+    };
+}
+
+// Files that the engine itself uses, under "engine/". These are mounted below every
+// other source, so games can override them.
+pub const ENGINE_FILES: EmbeddedFiles = &[
+    ("engine/present.wgsl", include_bytes!("res/present.wgsl")),
+    // The presenter samples a plain texture, which is bound the same way without binding arrays.
+    (
+        "engine/present.fallback.wgsl",
+        include_bytes!("res/present.wgsl"),
+    ),
+    ("engine/sprite.wgsl", include_bytes!("res/sprite.wgsl")),
+    (
+        "engine/sprite.fallback.wgsl",
+        include_bytes!("res/sprite.fallback.wgsl"),
+    ),
+];
+pub const ENGINE_FILES_PRIORITY: i32 = i32::MIN;
+
+// Somewhere that res paths can be loaded from.
+#[derive(Clone, Debug)]
+pub enum MountSource {
+    // A directory, relative paths are looked up in the working directory and then
+    // next to the executable.
+    Dir(PathBuf),
+    // A zip archive containing files at the same paths they'd have in res/.
+    #[cfg(feature = "zip_mounts")]
+    Zip(PathBuf),
+    Embedded(EmbeddedFiles),
+}
+
+trait Mount {
+    // Returns None if the mount doesn't contain the file.
+    fn read(&self, res_path: &str) -> Option<io::Result<Vec<u8>>>;
+
+    // The directory on disk that the mount reads from, if any.
+    fn dir(&self) -> Option<&Path> {
+        None
+    }
+}
+
+struct DirMount {
+    dir: PathBuf,
+}
+
+impl DirMount {
+    fn new(dir: &Path) -> Self {
+        let dir = if dir.is_relative() && !dir.exists() {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| Some(exe.parent()?.join(dir)))
+                .filter(|exe_dir| exe_dir.exists())
+                .unwrap_or_else(|| dir.into())
+        } else {
+            dir.into()
+        };
+
+        Self { dir }
+    }
+}
+
+impl Mount for DirMount {
+    fn read(&self, res_path: &str) -> Option<io::Result<Vec<u8>>> {
+        match std::fs::read(self.dir.join(res_path)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            result => Some(result),
+        }
+    }
+
+    fn dir(&self) -> Option<&Path> {
+        Some(&self.dir)
+    }
+}
+
+#[cfg(feature = "zip_mounts")]
+struct ZipMount {
+    // Reading from the archive needs mutable access, even when loading through a shared Vfs.
+    archive: std::sync::Mutex<zip::ZipArchive<io::BufReader<std::fs::File>>>,
+}
+
+#[cfg(feature = "zip_mounts")]
+impl ZipMount {
+    fn new(path: &Path) -> io::Result<Self> {
+        let file = io::BufReader::new(std::fs::File::open(path)?);
+        let archive = zip::ZipArchive::new(file)?;
+
+        Ok(Self {
+            archive: std::sync::Mutex::new(archive),
+        })
+    }
+}
+
+#[cfg(feature = "zip_mounts")]
+impl Mount for ZipMount {
+    fn read(&self, res_path: &str) -> Option<io::Result<Vec<u8>>> {
+        use std::io::Read;
+
+        let mut archive = self.archive.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut file = match archive.by_name(res_path) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return None,
+            Err(e) => return Some(Err(e.into())),
+        };
+
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        Some(file.read_to_end(&mut bytes).map(|_| bytes))
+    }
+}
+
+struct EmbeddedMount {
+    files: EmbeddedFiles,
+}
+
+impl Mount for EmbeddedMount {
+    fn read(&self, res_path: &str) -> Option<io::Result<Vec<u8>>> {
+        self.files
+            .iter()
+            .find(|(path, _)| *path == res_path)
+            .map(|(_, bytes)| Ok(bytes.to_vec()))
+    }
+}
+
+struct MountEntry {
+    priority: i32,
+    mount: Box<dyn Mount>,
+}
+
+// Loads res paths from a list of mounts, searching higher priority mounts first.
+// Between mounts with the same priority, the most recently mounted is searched first,
+// so that eg. mods can override files from the base game.
+#[derive(Default)]
+pub struct Vfs {
+    mounts: Vec<MountEntry>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Mount each source with the same priority, so later sources override earlier ones.
+    pub fn from_sources(sources: &[MountSource]) -> Result<Self, error::EngineError> {
+        let mut vfs = Self::new();
+
+        for source in sources {
+            vfs.mount(source, 0)?;
+        }
+
+        Ok(vfs)
+    }
+
+    pub fn mount(&mut self, source: &MountSource, priority: i32) -> Result<(), error::EngineError> {
+        let mount: Box<dyn Mount> = match source {
+            MountSource::Dir(dir) => Box::new(DirMount::new(dir)),
+            #[cfg(feature = "zip_mounts")]
+            MountSource::Zip(path) => Box::new(
+                ZipMount::new(path)
+                    .map_err(|e| error::EngineError::resource(&path.to_string_lossy(), e))?,
+            ),
+            MountSource::Embedded(files) => Box::new(EmbeddedMount { files }),
+        };
+
+        let index = self
+            .mounts
+            .iter()
+            .position(|m| m.priority <= priority)
+            .unwrap_or(self.mounts.len());
+        self.mounts.insert(index, MountEntry { priority, mount });
+
+        Ok(())
+    }
+
+    pub fn read(&self, res_path: &str) -> io::Result<Vec<u8>> {
+        let res_path = res_path.trim_start_matches("./");
+
+        // Res paths are relative to each mount, so they mustn't be able to reach outside of it.
+        let escapes_mount = Path::new(res_path).components().any(|c| {
+            matches!(
+                c,
+                Component::ParentDir | Component::RootDir | Component::Prefix(_)
+            )
+        });
+        if escapes_mount {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} isn't a relative path inside of the mounts", res_path),
+            ));
+        }
+
+        self.mounts
+            .iter()
+            .find_map(|m| m.mount.read(res_path))
+            .unwrap_or_else(|| {
+                Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} wasn't found in any mount", res_path),
+                ))
+            })
+    }
+
+    pub fn read_to_string(&self, res_path: &str) -> io::Result<String> {
+        String::from_utf8(self.read(res_path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn read_image(&self, res_path: &str) -> Result<image::DynamicImage, error::EngineError> {
+        let bytes = self
+            .read(res_path)
+            .map_err(|e| error::EngineError::resource(res_path, e))?;
+
+        image::load_from_memory(&bytes).map_err(|e| error::EngineError::resource(res_path, e))
+    }
+
+    // The directories on disk that files are loaded from.
+    pub fn dirs(&self) -> impl Iterator<Item = &Path> {
+        self.mounts.iter().filter_map(|m| m.mount.dir())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: EmbeddedFiles = &[("a.txt", b"base a"), ("b.txt", b"base b")];
+    const MOD: EmbeddedFiles = &[("a.txt", b"mod a")];
+    const PATCH: EmbeddedFiles = &[("a.txt", b"patch a"), ("b.txt", b"patch b")];
+
+    fn read(vfs: &Vfs, res_path: &str) -> String {
+        vfs.read_to_string(res_path).unwrap()
+    }
+
+    #[test]
+    fn later_sources_override_earlier_ones() {
+        let vfs =
+            Vfs::from_sources(&[MountSource::Embedded(BASE), MountSource::Embedded(MOD)]).unwrap();
+
+        assert_eq!(read(&vfs, "a.txt"), "mod a");
+        assert_eq!(read(&vfs, "b.txt"), "base b");
+    }
+
+    #[test]
+    fn higher_priorities_are_searched_first() {
+        let mut vfs = Vfs::new();
+        vfs.mount(&MountSource::Embedded(PATCH), 1).unwrap();
+        vfs.mount(&MountSource::Embedded(BASE), 0).unwrap();
+        vfs.mount(&MountSource::Embedded(MOD), 0).unwrap();
+
+        assert_eq!(read(&vfs, "a.txt"), "patch a");
+        assert_eq!(read(&vfs, "b.txt"), "patch b");
+
+        // Engine files are below everything else.
+        vfs.mount(&MountSource::Embedded(ENGINE_FILES), ENGINE_FILES_PRIORITY)
+            .unwrap();
+        assert_eq!(read(&vfs, "a.txt"), "patch a");
+        assert!(vfs.read("engine/present.wgsl").is_ok());
+        assert!(vfs.read("engine/sprite.fallback.wgsl").is_ok());
+    }
+
+    #[test]
+    fn missing_files_are_not_found() {
+        let vfs = Vfs::from_sources(&[MountSource::Embedded(BASE)]).unwrap();
+
+        assert_eq!(read(&vfs, "./a.txt"), "base a");
+        assert_eq!(
+            vfs.read("c.txt").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn paths_outside_of_mounts_are_rejected() {
+        let dir = std::env::temp_dir().join(format!("vfs_escape_{}", std::process::id()));
+        let mount_dir = dir.join("res");
+        std::fs::create_dir_all(&mount_dir).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let vfs = Vfs::from_sources(&[MountSource::Dir(mount_dir)]).unwrap();
+
+        let absolute_path = dir.join("secret.txt");
+        for res_path in [
+            "../secret.txt",
+            "sub/../../secret.txt",
+            absolute_path.to_str().unwrap(),
+        ] {
+            assert_eq!(
+                vfs.read(res_path).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "{}",
+                res_path
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "zip_mounts")]
+    #[test]
+    fn zip_mounts_are_searched_by_priority() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("vfs_zip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let zip_path = dir.join("mod.zip");
+
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        for (res_path, contents) in [("a.txt", "zip a"), ("sub/c.txt", "zip c")] {
+            writer
+                .start_file(res_path, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut vfs = Vfs::from_sources(&[
+            MountSource::Embedded(BASE),
+            MountSource::Zip(zip_path.clone()),
+        ])
+        .unwrap();
+        assert_eq!(read(&vfs, "a.txt"), "zip a");
+        assert_eq!(read(&vfs, "b.txt"), "base b");
+        assert_eq!(read(&vfs, "sub/c.txt"), "zip c");
+        assert_eq!(
+            vfs.read("c.txt").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        vfs.mount(&MountSource::Embedded(PATCH), 1).unwrap();
+        assert_eq!(read(&vfs, "a.txt"), "patch a");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -51,15 +51,14 @@ impl Entity {
 
     pub fn check_entity_collisions(
         pos: HorizontalPoint<f32>,
-        chunk_entities: &Vec<Vec<Entity>>,
+        chunk_entities: &[Vec<Entity>],
     ) -> Option<(usize, usize)> {
         for i in 0..4 {
             let corner_pos = Self::get_corner_positions(pos, i);
             let block_x = corner_pos.x.floor() as i32;
             let chunk_i = ((block_x >> 3) % 2) as usize;
 
-            for ei in 0..chunk_entities[chunk_i].len() {
-                let e = &chunk_entities[chunk_i][ei];
+            for (ei, e) in chunk_entities[chunk_i].iter().enumerate() {
                 let mut collided = true;
 
                 for j in 0..4 {
@@ -1,6 +1,6 @@
 use crate::engine::{
-    camera, engine_handle, game, input, instance, model, render_handle, texture, texture_array,
-    vertex,
+    assets, buffer, camera, config, engine_handle, game, input, instance, model, presenter,
+    render_handle, sprite, texture_array,
 };
 use crate::game::voxels::blocks;
 use crate::game::{
@@ -9,93 +9,30 @@ use crate::game::{
 };
 use cgmath::prelude::*;
 
-const SCREEN_VERTICES: &[vertex::Vertex] = &[
-    vertex::Vertex {
-        position: [-0.5, 0.5, 0.0],
-        tex_coords: [0.0, 0.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-    vertex::Vertex {
-        position: [-0.5, -0.5, 0.0],
-        tex_coords: [0.0, 1.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-    vertex::Vertex {
-        position: [0.5, -0.5, 0.0],
-        tex_coords: [1.0, 1.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-    vertex::Vertex {
-        position: [0.5, 0.5, 0.0],
-        tex_coords: [1.0, 0.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-];
-
-const SCREEN_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
-
 pub const SPRITE_HALF_HEIGHT: f32 = 1.14;
 pub const SPRITE_HALF_WIDTH: f32 = 0.5;
-const SPRITE_VERTICES: &[vertex::Vertex] = &[
-    vertex::Vertex {
-        position: [-SPRITE_HALF_WIDTH, SPRITE_HALF_HEIGHT, 0.0],
-        tex_coords: [0.0, 0.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-    vertex::Vertex {
-        position: [-SPRITE_HALF_WIDTH, -SPRITE_HALF_HEIGHT, 0.0],
-        tex_coords: [0.0, 1.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-    vertex::Vertex {
-        position: [SPRITE_HALF_WIDTH, -SPRITE_HALF_HEIGHT, 0.0],
-        tex_coords: [1.0, 1.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-    vertex::Vertex {
-        position: [SPRITE_HALF_WIDTH, SPRITE_HALF_HEIGHT, 0.0],
-        tex_coords: [1.0, 0.0],
-        tex_index: 0,
-        color: [1.0, 1.0, 1.0],
-    },
-];
-
-const SPRITE_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
-
 const CAM_OFFSET: cgmath::Vector3<f32> = cgmath::Vector3::new(0.0, 0.0, 4.25);
 const CAM_POS_OFFSET: cgmath::Vector3<f32> = cgmath::Vector3::new(0.0, 8.0, 4.0);
 
 const SCREEN_SIZE: u32 = 64;
+const INPUT_MAP_FILE_NAME: &str = "input_map.cfg";
 const BLOCK_SIZE: u32 = 8;
 
 pub struct LowRezGameState {
     fixed_update_count: u32,
-    camera: camera::CameraHandle,
     v_camera: camera::CameraHandle,
     v_camera_pos: cgmath::Vector3<f32>,
     v_camera_target: cgmath::Vector3<f32>,
     block_tex_array: texture_array::TextureArray,
     sprite_tex_array: texture_array::TextureArray,
-    render_texture: texture::Texture,
-    screen_model: model::Model,
-    sprite_model: model::Model,
-    screen_pipeline: wgpu::RenderPipeline,
-    chunk_pipeline: wgpu::RenderPipeline,
-    sprite_pipeline: wgpu::RenderPipeline,
-    screen_instance_buffer: wgpu::Buffer,
+    presenter: presenter::LowResPresenter,
+    chunk_pipeline: assets::Handle<wgpu::RenderPipeline>,
+    sprite_batch: sprite::SpriteBatch,
     entities: Vec<entity::Entity>,
-    entity_instance_buffer: wgpu::Buffer,
     chunks: [chunk::Chunk; 2],
     chunk_models: Vec<model::Model>,
     chunk_instances: Vec<instance::Instance>,
-    chunk_instance_buffers: Vec<wgpu::Buffer>,
+    chunk_instance_buffers: Vec<buffer::DynamicBuffer<instance::InstanceRaw>>,
     chunk_entities: Vec<Vec<entity::Entity>>,
     chunk_entity_dirs: Vec<Vec<i32>>,
     pan_distance: f32,
@@ -103,18 +40,24 @@ pub struct LowRezGameState {
 
 pub struct LowRezGame {
     fixed_update_rate: u32,
+    world_seed: u64,
+    paused: bool,
     state: Option<LowRezGameState>,
 }
 
 impl LowRezGame {
-    pub fn new(fixed_update_rate: u32) -> Self {
+    pub fn with_seed(fixed_update_rate: u32, world_seed: u64) -> Self {
         Self {
             fixed_update_rate,
+            world_seed,
+            paused: false,
             state: None,
         }
     }
 
     fn render_game(state: &LowRezGameState, handle: &mut render_handle::RenderHandle) {
+        let assets = handle.assets();
+        let chunk_pipeline = handle.get_pipeline(state.chunk_pipeline);
         let (mut render_pass, camera) = handle.begin_render_pass(
             state.v_camera,
             wgpu::Color {
@@ -123,60 +66,20 @@ impl LowRezGame {
                 b: 0.0,
                 a: 1.0,
             },
-            Some(&state.render_texture),
+            Some(state.presenter.target()),
         );
-        render_pass.set_pipeline(&state.chunk_pipeline);
+        render_pass.set_pipeline(chunk_pipeline);
         render_pass.set_bind_group(0, state.block_tex_array.bind_group(), &[]);
         render_pass.set_bind_group(1, camera.bind_group(), &[]);
 
         for i in 0..state.chunk_models.len() {
-            render_pass.set_vertex_buffer(0, state.chunk_models[i].vertices().slice(..));
-            render_pass.set_vertex_buffer(1, state.chunk_instance_buffers[i].slice(..));
-            render_pass.set_index_buffer(
-                state.chunk_models[i].indices().slice(..),
-                wgpu::IndexFormat::Uint16,
-            );
-            render_pass.draw_indexed(0..state.chunk_models[i].num_indices(), 0, 0..1);
+            render_pass.set_vertex_buffer(1, state.chunk_instance_buffers[i].slice());
+            render_handle::RenderHandle::draw_model(&mut render_pass, &state.chunk_models[i], 0..1);
         }
 
-        render_pass.set_pipeline(&state.sprite_pipeline);
-        render_pass.set_bind_group(0, state.sprite_tex_array.bind_group(), &[]);
-        render_pass.set_bind_group(1, camera.bind_group(), &[]);
-        render_pass.set_vertex_buffer(0, state.sprite_model.vertices().slice(..));
-        render_pass.set_index_buffer(
-            state.sprite_model.indices().slice(..),
-            wgpu::IndexFormat::Uint16,
-        );
-
-        assert_eq!(state.chunks.len(), 2);
-        let num_entities = (state.entities.len()
-            + state.chunk_entities[0].len()
-            + state.chunk_entities[1].len()) as u32;
-        render_pass.set_vertex_buffer(1, state.entity_instance_buffer.slice(..));
-        render_pass.draw_indexed(0..state.screen_model.num_indices(), 0, 0..num_entities);
-    }
-
-    fn render_screen(state: &LowRezGameState, handle: &mut render_handle::RenderHandle) {
-        let (mut render_pass, camera) = handle.begin_render_pass(
-            state.camera,
-            wgpu::Color {
-                r: 0.0,
-                g: 0.0,
-                b: 0.0,
-                a: 1.0,
-            },
-            None,
-        );
-        render_pass.set_pipeline(&state.screen_pipeline);
-        render_pass.set_bind_group(0, state.render_texture.bind_group().unwrap(), &[]);
-        render_pass.set_bind_group(1, camera.bind_group(), &[]);
-        render_pass.set_vertex_buffer(0, state.screen_model.vertices().slice(..));
-        render_pass.set_vertex_buffer(1, state.screen_instance_buffer.slice(..));
-        render_pass.set_index_buffer(
-            state.screen_model.indices().slice(..),
-            wgpu::IndexFormat::Uint16,
-        );
-        render_pass.draw_indexed(0..state.screen_model.num_indices(), 0, 0..1);
+        state
+            .sprite_batch
+            .render(&mut render_pass, assets, &state.sprite_tex_array, camera);
     }
 
     pub fn round_to_pixel(x: f32) -> f32 {
@@ -191,7 +94,7 @@ impl LowRezGame {
 
         for chunk in chunks {
             let chunk_model_data = mesh_generator::generate_mesh_data(chunk);
-            let chunk_model = handle.create_model(
+            let chunk_model = handle.create_compact_model(
                 chunk_model_data.vertices.as_slice(),
                 chunk_model_data.indices.as_slice(),
             );
@@ -202,59 +105,158 @@ impl LowRezGame {
         models
     }
 
+    fn update_chunk_models(
+        chunks: &[chunk::Chunk],
+        chunk_models: &mut [model::Model],
+        handle: &mut engine_handle::EngineHandle,
+    ) {
+        for (chunk, chunk_model) in chunks.iter().zip(chunk_models) {
+            let chunk_model_data = mesh_generator::generate_mesh_data(chunk);
+            handle.update_compact_model(
+                chunk_model,
+                chunk_model_data.vertices.as_slice(),
+                chunk_model_data.indices.as_slice(),
+            );
+        }
+    }
+
     fn create_chunk_instance_buffers(
-        chunk_instances: &Vec<instance::Instance>,
+        chunk_instances: &[instance::Instance],
         handle: &mut engine_handle::EngineHandle,
-    ) -> Vec<wgpu::Buffer> {
+    ) -> Vec<buffer::DynamicBuffer<instance::InstanceRaw>> {
         chunk_instances
             .iter()
-            .map(|i| handle.create_instance_buffer(&vec![i]))
+            .map(|i| {
+                handle.create_dynamic_buffer(
+                    &[i.to_raw()],
+                    wgpu::BufferUsages::VERTEX,
+                    Some("Chunk Instance Buffer"),
+                )
+            })
             .collect()
     }
 
-    fn create_entity_instance_buffer(
-        entities: &Vec<entity::Entity>,
-        chunks: &[chunk::Chunk; 2],
-        chunk_entities: &Vec<Vec<entity::Entity>>,
+    fn update_chunk_instance_buffers(
+        chunk_instances: &[instance::Instance],
+        chunk_instance_buffers: &mut [buffer::DynamicBuffer<instance::InstanceRaw>],
         handle: &mut engine_handle::EngineHandle,
-    ) -> wgpu::Buffer {
-        let mut raw_entities = std::collections::HashMap::new();
+    ) {
+        for (i, buffer) in chunk_instances.iter().zip(chunk_instance_buffers) {
+            handle.update_dynamic_buffer(buffer, &[i.to_raw()]);
+        }
+    }
+
+    fn draw_entity_sprites(
+        entities: &[entity::Entity],
+        chunks: &[chunk::Chunk; 2],
+        chunk_entities: &[Vec<entity::Entity>],
+        sprite_batch: &mut sprite::SpriteBatch,
+    ) {
+        let mut used_depths = std::collections::HashSet::new();
 
         fn hash_depth(depth: f32, x: f32) -> i32 {
             ((depth * 1000.0).floor() + (x / (SPRITE_HALF_WIDTH * 10.0)).round()) as i32
         }
 
-        fn add_raw_entities(
-            entities: &Vec<entity::Entity>,
-            raw_entities: &mut std::collections::HashMap<i32, instance::InstanceRaw>,
-        ) {
+        // Offset entities that would overlap at the same depth, to prevent z-fighting.
+        let mut draw_entities = |entities: &[entity::Entity]| {
             for e in entities {
                 let mut z_off = 0.0;
 
-                while raw_entities.contains_key(&hash_depth(e.pos.z + z_off, e.pos.x)) {
+                while !used_depths.insert(hash_depth(e.pos.z + z_off, e.pos.x)) {
                     z_off += 0.001;
                 }
 
-                raw_entities.insert(
-                    hash_depth(e.pos.z + z_off, e.pos.x),
-                    e.instance
-                        .to_raw_with_offset(cgmath::Vector3::new(0.0, 0.0, z_off)),
-                );
+                sprite_batch.draw(sprite::Sprite::new(
+                    e.instance.tex_index as u32,
+                    e.instance.position + cgmath::Vector3::new(0.0, 0.0, z_off),
+                    cgmath::Vector2::new(SPRITE_HALF_WIDTH * 2.0, SPRITE_HALF_HEIGHT * 2.0),
+                ));
             }
+        };
+
+        draw_entities(entities);
+
+        for entities_in_chunk in chunk_entities.iter().take(chunks.len()) {
+            draw_entities(entities_in_chunk);
         }
+    }
+
+    fn create_default_input_map() -> input::InputMap {
+        use input::{GamepadAxis, GamepadButton, InputSource};
+        use winit::event::VirtualKeyCode;
+
+        let mut input_map = input::InputMap::new();
+        input_map.bind_axis("move_x", InputSource::Key(VirtualKeyCode::Left), -1.0);
+        input_map.bind_axis("move_x", InputSource::Key(VirtualKeyCode::Right), 1.0);
+        input_map.bind_axis(
+            "move_x",
+            InputSource::GamepadButton(GamepadButton::DPadLeft),
+            -1.0,
+        );
+        input_map.bind_axis(
+            "move_x",
+            InputSource::GamepadButton(GamepadButton::DPadRight),
+            1.0,
+        );
+        input_map.bind_axis(
+            "move_x",
+            InputSource::GamepadAxis(GamepadAxis::LeftStickX),
+            1.0,
+        );
+        input_map.bind_axis("move_z", InputSource::Key(VirtualKeyCode::Up), -1.0);
+        input_map.bind_axis("move_z", InputSource::Key(VirtualKeyCode::Down), 1.0);
+        input_map.bind_axis(
+            "move_z",
+            InputSource::GamepadButton(GamepadButton::DPadUp),
+            -1.0,
+        );
+        input_map.bind_axis(
+            "move_z",
+            InputSource::GamepadButton(GamepadButton::DPadDown),
+            1.0,
+        );
+        // Pushing the stick up gives a positive value, which should move away from the camera.
+        input_map.bind_axis(
+            "move_z",
+            InputSource::GamepadAxis(GamepadAxis::LeftStickY),
+            -1.0,
+        );
+        input_map.bind_axis2("move", "move_x", "move_z");
+        input_map.bind_action("fire", InputSource::Key(VirtualKeyCode::Space));
+        input_map.bind_action("fire", InputSource::GamepadButton(GamepadButton::South));
 
-        add_raw_entities(entities, &mut raw_entities);
+        input_map
+    }
 
-        for i in 0..chunks.len() {
-            add_raw_entities(&chunk_entities[i], &mut raw_entities);
+    // Load the player's bindings, or save the defaults so that they can be edited.
+    // Headless runs always use the defaults and don't touch the file.
+    fn load_input_map(handle: &engine_handle::EngineHandle) -> input::InputMap {
+        if handle.is_headless() {
+            return Self::create_default_input_map();
         }
 
-        let mut sorted_raw_entities = raw_entities.iter().collect::<Vec<_>>();
-        sorted_raw_entities.sort_by_key(|i| i.0);
+        let path = config::config_dir().join(INPUT_MAP_FILE_NAME);
 
-        let raw_instances = sorted_raw_entities.iter().map(|i| *i.1).collect::<Vec<_>>();
+        match input::InputMap::load(&path) {
+            Ok(input_map) => input_map,
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    let input_map = Self::create_default_input_map();
+                    let saved = std::fs::create_dir_all(config::config_dir())
+                        .and_then(|_| input_map.save(&path));
 
-        handle.create_instance_buffer_from_raw(&raw_instances)
+                    if let Err(e) = saved {
+                        log::warn!("Failed to save default input map: {}", e);
+                    }
+
+                    input_map
+                } else {
+                    log::warn!("Failed to load input map, using defaults: {}", e);
+                    Self::create_default_input_map()
+                }
+            }
+        }
     }
 
     fn update_camera(
@@ -277,20 +279,11 @@ impl LowRezGame {
 
 impl game::Game for LowRezGame {
     fn start(&mut self, handle: &mut engine_handle::EngineHandle) {
-        let camera = handle.create_camera(
-            (0.0, 0.0, 2.0).into(),
-            (0.0, 0.0, 0.0).into(),
-            cgmath::Vector3::unit_y(),
-            Box::new(camera::OrthographicProjection {
-                width: 1.0,
-                height: 1.0,
-                fixed_aspect_ratio: false,
-                z_near: 0.1,
-                z_far: 100.0,
-            }),
-            None,
-            None,
-        );
+        let world_seed = self.world_seed;
+        log::info!("Generating world with seed: {}", world_seed);
+
+        let input_map = Self::load_input_map(handle);
+        handle.set_input_map(input_map);
 
         let v_camera_pos = CAM_OFFSET + CAM_POS_OFFSET;
         let v_camera_target = CAM_OFFSET;
@@ -326,12 +319,11 @@ impl game::Game for LowRezGame {
 
         let sprite_tex_array = handle.create_texture_array(sprite_textures);
 
-        let render_texture = handle.create_texture(
+        let presenter = presenter::LowResPresenter::new(
+            handle,
             SCREEN_SIZE,
             SCREEN_SIZE,
-            wgpu::TextureFormat::Bgra8UnormSrgb,
-            wgpu::TextureUsages::RENDER_ATTACHMENT,
-            Some("render_texture"),
+            presenter::ScalingMode::Integer,
         );
 
         let mut chunks = [
@@ -352,24 +344,22 @@ impl game::Game for LowRezGame {
             },
         ];
 
-        let mut chunk_entities: Vec<Vec<entity::Entity>> = Vec::<Vec<_>>::new();
-        chunk_entities.push(Vec::<entity::Entity>::new());
-        chunk_entities.push(Vec::<entity::Entity>::new());
-        let mut chunk_entity_dirs: Vec<Vec<i32>> = Vec::<Vec<_>>::new();
-        chunk_entity_dirs.push(Vec::<i32>::new());
-        chunk_entity_dirs.push(Vec::<i32>::new());
+        let mut chunk_entities: Vec<Vec<entity::Entity>> = vec![Vec::new(), Vec::new()];
+        let mut chunk_entity_dirs: Vec<Vec<i32>> = vec![Vec::new(), Vec::new()];
 
+        let world_x = chunk_instances[0].position.x as i32;
         chunks[0].generate(
-            &mut rand::thread_rng(),
+            &mut chunk::Chunk::create_rng(world_seed, world_x),
             true,
-            chunk_instances[0].position.x as i32,
+            world_x,
             &mut chunk_entities[0],
             &mut chunk_entity_dirs[0],
         );
+        let world_x = chunk_instances[1].position.x as i32;
         chunks[1].generate(
-            &mut rand::thread_rng(),
+            &mut chunk::Chunk::create_rng(world_seed, world_x),
             false,
-            chunk_instances[1].position.x as i32,
+            world_x,
             &mut chunk_entities[1],
             &mut chunk_entity_dirs[1],
         );
@@ -378,56 +368,32 @@ impl game::Game for LowRezGame {
 
         let chunk_instance_buffers = Self::create_chunk_instance_buffers(&chunk_instances, handle);
 
-        let screen_instances = vec![instance::Instance {
-            position: cgmath::Vector3::new(0.0, 0.0, 0.0),
-            rotation: cgmath::Quaternion::one(),
-            tex_index: 0,
-        }];
-        let screen_instance_buffer = handle.create_instance_buffer(&screen_instances);
-
         let entities = vec![entity::Entity::new(3.5, 4.5, 0)];
-        let entity_instance_buffer =
-            Self::create_entity_instance_buffer(&entities, &chunks, &chunk_entities, handle);
 
-        let screen_pipeline = handle.create_pipeline(
-            "shader.wgsl",
-            &[render_texture.bind_group_layout().unwrap()],
-            Some(camera),
-        );
         let chunk_pipeline = handle.create_pipeline(
             "shader.wgsl",
             &[block_tex_array.bind_group_layout()],
-            Some(camera),
-        );
-        let sprite_pipeline = handle.create_pipeline(
-            "shader.wgsl",
-            &[sprite_tex_array.bind_group_layout()],
-            Some(camera),
+            Some(v_camera),
         );
 
-        let screen_model = handle.create_model(SCREEN_VERTICES, SCREEN_INDICES);
-        let sprite_model = handle.create_model(SPRITE_VERTICES, SPRITE_INDICES);
+        let mut sprite_batch = sprite::SpriteBatch::new(handle, &sprite_tex_array, v_camera);
+        Self::draw_entity_sprites(&entities, &chunks, &chunk_entities, &mut sprite_batch);
+        sprite_batch.prepare(handle);
 
         self.state = Some(LowRezGameState {
             fixed_update_count: 0,
             pan_distance: 0.0,
             chunk_entities,
             chunk_entity_dirs,
-            camera,
             v_camera,
             v_camera_pos,
             v_camera_target,
-            screen_pipeline,
             chunk_pipeline,
-            sprite_pipeline,
-            screen_model,
-            sprite_model,
+            sprite_batch,
             block_tex_array,
             sprite_tex_array,
-            render_texture,
-            screen_instance_buffer,
+            presenter,
             entities,
-            entity_instance_buffer,
             chunks,
             chunk_models,
             chunk_instances,
@@ -436,38 +402,21 @@ impl game::Game for LowRezGame {
     }
 
     fn fixed_update(&mut self, input: &input::Input, handle: &mut engine_handle::EngineHandle) {
-        if let Some(state) = &mut self.state {
-            use winit::event::VirtualKeyCode;
+        let world_seed = self.world_seed;
 
+        if let Some(state) = &mut self.state {
             state.fixed_update_count = state.fixed_update_count.overflowing_add(1).0;
 
-            let mut dir_x = 0;
-            let mut dir_z = 0;
-
-            if input.is_key_held(VirtualKeyCode::Left) {
-                dir_x = -1;
-            }
-
-            if input.is_key_held(VirtualKeyCode::Right) {
-                dir_x = 1;
-            }
-
-            if input.is_key_held(VirtualKeyCode::Down) {
-                dir_z = 1;
-            }
-
-            if input.is_key_held(VirtualKeyCode::Up) {
-                dir_z = -1;
-            }
+            let movement = input.axis2("move");
 
             let speed = 4.0 / 64.0;
-            let move_x = dir_x as f32 * speed;
+            let move_x = movement.x * speed;
 
             if state.entities[0].pos.x + move_x > state.v_camera_pos.x - 3.5 {
                 state.entities[0].move_x(move_x, &state.chunks);
             }
 
-            state.entities[0].move_z(dir_z as f32 * speed, &state.chunks);
+            state.entities[0].move_z(movement.y * speed, &state.chunks);
 
             let player_pos_vec = cgmath::Vector3::new(state.entities[0].pos.x, 0.0, 0.0);
 
@@ -485,17 +434,21 @@ impl game::Game for LowRezGame {
                         chunk_entity_dirs,
                         ..
                     } = state;
+                    let world_x = state.chunk_instances[last_chunk_i].position.x as i32;
                     chunks[last_chunk_i].generate(
-                        &mut rand::thread_rng(),
+                        &mut chunk::Chunk::create_rng(world_seed, world_x),
                         false,
-                        state.chunk_instances[last_chunk_i].position.x as i32,
+                        world_x,
                         &mut chunk_entities[last_chunk_i],
                         &mut chunk_entity_dirs[last_chunk_i],
                     );
 
-                    state.chunk_instance_buffers =
-                        Self::create_chunk_instance_buffers(&state.chunk_instances, handle);
-                    state.chunk_models = Self::create_chunk_models(&state.chunks, handle);
+                    Self::update_chunk_instance_buffers(
+                        &state.chunk_instances,
+                        &mut state.chunk_instance_buffers,
+                        handle,
+                    );
+                    Self::update_chunk_models(&state.chunks, &mut state.chunk_models, handle);
                 }
 
                 Self::update_camera(
@@ -507,7 +460,7 @@ impl game::Game for LowRezGame {
                 );
             }
 
-            if input.was_key_pressed(VirtualKeyCode::Space) {
+            if input.action_pressed("fire") {
                 state.entities.push(entity::Entity::new(
                     state.entities[0].pos.x,
                     state.entities[0].pos.z,
@@ -525,7 +478,7 @@ impl game::Game for LowRezGame {
             // Check bullet collisions (entity 0 is the player, all others are bullets).
             for i in (1..state.entities.len()).rev() {
                 if let Some(hit) =
-                    entity::Entity::check_entity_collisions(state.entities[i].pos, &chunk_entities)
+                    entity::Entity::check_entity_collisions(state.entities[i].pos, chunk_entities)
                 {
                     chunk_entities[hit.0].remove(hit.1);
                     chunk_entity_dirs[hit.0].remove(hit.1);
@@ -554,9 +507,11 @@ impl game::Game for LowRezGame {
                                 1,
                                 hit_block_z,
                             );
-                            state.chunk_instance_buffers =
-                                Self::create_chunk_instance_buffers(&state.chunk_instances, handle);
-                            state.chunk_models = Self::create_chunk_models(chunks, handle);
+                            Self::update_chunk_models(
+                                &chunks[hit_chunk..=hit_chunk],
+                                &mut state.chunk_models[hit_chunk..=hit_chunk],
+                                handle,
+                            );
                             break;
                         }
                     }
@@ -581,24 +536,26 @@ impl game::Game for LowRezGame {
             }
 
             // Check player collisions.
-            if let Some(_) =
-                entity::Entity::check_entity_collisions(state.entities[0].pos, &chunk_entities)
+            if entity::Entity::check_entity_collisions(state.entities[0].pos, chunk_entities)
+                .is_some()
             {
                 state.chunk_instances[0].position = cgmath::Vector3::new(0.0, 0.0, 0.0);
                 state.chunk_instances[1].position = cgmath::Vector3::new(8.0, 0.0, 0.0);
 
+                let world_x = state.chunk_instances[0].position.x as i32;
                 chunks[0].generate(
-                    &mut rand::thread_rng(),
+                    &mut chunk::Chunk::create_rng(world_seed, world_x),
                     true,
-                    state.chunk_instances[0].position.x as i32,
+                    world_x,
                     &mut chunk_entities[0],
                     &mut chunk_entity_dirs[0],
                 );
 
+                let world_x = state.chunk_instances[1].position.x as i32;
                 chunks[1].generate(
-                    &mut rand::thread_rng(),
+                    &mut chunk::Chunk::create_rng(world_seed, world_x),
                     false,
-                    state.chunk_instances[1].position.x as i32,
+                    world_x,
                     &mut chunk_entities[1],
                     &mut chunk_entity_dirs[1],
                 );
@@ -616,28 +573,109 @@ impl game::Game for LowRezGame {
                     player_pos_vec,
                 );
 
-                state.chunk_instance_buffers =
-                    Self::create_chunk_instance_buffers(&state.chunk_instances, handle);
-                state.chunk_models = Self::create_chunk_models(chunks, handle);
+                Self::update_chunk_instance_buffers(
+                    &state.chunk_instances,
+                    &mut state.chunk_instance_buffers,
+                    handle,
+                );
+                Self::update_chunk_models(chunks, &mut state.chunk_models, handle);
             }
 
-            state.entity_instance_buffer = Self::create_entity_instance_buffer(
+            Self::draw_entity_sprites(
                 &state.entities,
-                &chunks,
-                &chunk_entities,
-                handle,
+                chunks,
+                chunk_entities,
+                &mut state.sprite_batch,
             );
+            state.sprite_batch.prepare(handle);
+        }
+    }
+
+    fn update(
+        &mut self,
+        input: &input::Input,
+        handle: &mut engine_handle::EngineHandle,
+        _delta_time: f32,
+    ) {
+        if input.was_key_pressed(winit::event::VirtualKeyCode::Escape) {
+            handle.request_exit();
+        }
+
+        if input.was_key_pressed(winit::event::VirtualKeyCode::P) {
+            self.paused = !self.paused;
+            handle.set_paused(self.paused);
+        }
+
+        if self.paused && input.was_key_pressed(winit::event::VirtualKeyCode::Period) {
+            handle.step_fixed_update();
+        }
+
+        if let Some(state) = &mut self.state {
+            state.presenter.update(handle);
+        }
+
+        let alt_held = input.is_key_held(winit::event::VirtualKeyCode::LAlt)
+            || input.is_key_held(winit::event::VirtualKeyCode::RAlt);
+
+        if input.was_key_pressed(winit::event::VirtualKeyCode::F11)
+            || (alt_held && input.was_key_pressed(winit::event::VirtualKeyCode::Return))
+        {
+            // The window may start fullscreen from its config, so toggle its actual state.
+            handle.set_fullscreen(if handle.is_fullscreen() {
+                config::WindowMode::Windowed
+            } else {
+                config::WindowMode::Borderless
+            });
         }
     }
 
     fn render(&mut self, handle: &mut render_handle::RenderHandle) {
         if let Some(state) = &mut self.state {
             LowRezGame::render_game(state, handle);
-            LowRezGame::render_screen(state, handle);
+            state.presenter.present(handle);
         }
     }
 
     fn get_fixed_update_rate(&self) -> u32 {
         self.fixed_update_rate
     }
+
+    fn get_config(&self) -> config::EngineConfig {
+        config::EngineConfig {
+            window_size: Some((SCREEN_SIZE * 8, SCREEN_SIZE * 8)),
+            min_window_size: Some((SCREEN_SIZE, SCREEN_SIZE)),
+            icon_res_path: Some("player.png".into()),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{golden, replay::RecordedInput};
+    use winit::event::ElementState::{Pressed, Released};
+    use winit::event::VirtualKeyCode;
+
+    #[test]
+    fn seeded_worlds_match_their_golden() {
+        let mut test = golden::GoldenTest::new("lowrez_game_seed_1", 30);
+        test.width = SCREEN_SIZE;
+        test.height = SCREEN_SIZE;
+        test.force_fallback_adapter = true;
+        // Walk diagonally, then fire, so that the player's movement and bullets are covered too.
+        test.script = vec![
+            RecordedInput::key(2, VirtualKeyCode::Right, Pressed),
+            RecordedInput::key(5, VirtualKeyCode::Down, Pressed),
+            RecordedInput::key(12, VirtualKeyCode::Down, Released),
+            RecordedInput::key(20, VirtualKeyCode::Right, Released),
+            RecordedInput::key(24, VirtualKeyCode::Space, Pressed),
+            RecordedInput::key(25, VirtualKeyCode::Space, Released),
+        ];
+
+        match test.run(Box::new(LowRezGame::with_seed(60, 1))) {
+            Ok(golden::GoldenResult::Matched | golden::GoldenResult::Updated(_)) => {}
+            result => panic!("Golden test failed: {:?}", result),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use crate::game::{entity, voxels::blocks};
 use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
 
 pub struct Chunk {
     width: u32,
@@ -20,9 +21,17 @@ impl Chunk {
         }
     }
 
-    pub fn generate(
+    // Chunks are seeded from the world seed and their position, so that a
+    // chunk always regenerates identically for a given seed. ChaCha8Rng's output is
+    // fixed across platforms and rand versions, unlike StdRng's.
+    pub fn create_rng(world_seed: u64, world_x: i32) -> ChaCha8Rng {
+        let position_hash = (world_x as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        ChaCha8Rng::seed_from_u64(world_seed ^ position_hash)
+    }
+
+    pub fn generate<R: Rng>(
         &mut self,
-        rng: &mut ThreadRng,
+        rng: &mut R,
         spawn_chunk: bool,
         world_x: i32,
         entities: &mut Vec<entity::Entity>,
@@ -120,3 +129,43 @@ impl Chunk {
         self.world_height
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_rng_output_is_stable() {
+        let mut rng = Chunk::create_rng(1234, 8);
+        let values = [rng.next_u64(), rng.next_u64(), rng.next_u64()];
+
+        assert_eq!(
+            values,
+            [
+                10006645121969472054,
+                2983411878675947387,
+                1929582572108345974
+            ]
+        );
+    }
+
+    #[test]
+    fn generate_is_repeatable_for_a_seed() {
+        let generate = || {
+            let mut chunk = Chunk::new(8, 2, 11, 2);
+            let mut entities = Vec::new();
+            let mut entity_dirs = Vec::new();
+            chunk.generate(
+                &mut Chunk::create_rng(1234, 8),
+                false,
+                8,
+                &mut entities,
+                &mut entity_dirs,
+            );
+
+            (chunk.blocks, entities.len())
+        };
+
+        assert!(generate() == generate());
+    }
+}
@@ -7,7 +7,7 @@ const MIN_BLOCK_TINT_BRIGHTNESS: f32 = 0.1;
 
 pub struct MeshData {
     pub vertices: Vec<vertex::Vertex>,
-    pub indices: Vec<u16>,
+    pub indices: Vec<u32>,
 }
 
 pub fn generate_mesh_data(chunk: &chunk::Chunk) -> MeshData {
@@ -63,7 +63,7 @@ fn generate_face(
     let texture_index = (block as u32) - 1;
 
     let mesh_side = &cube_mesh::MESH_SIDES[face as usize];
-    let current_vertex_count = mesh_data.vertices.len() as u16;
+    let current_vertex_count = mesh_data.vertices.len() as u32;
 
     for i in 0..mesh_side.vertices.len() {
         let mut new_vertex = mesh_side.vertices[i];
@@ -86,6 +86,6 @@ fn generate_face(
     for i in 0..mesh_side.indices.len() {
         mesh_data
             .indices
-            .push(mesh_side.indices[i] + current_vertex_count);
+            .push(mesh_side.indices[i] as u32 + current_vertex_count);
     }
 }
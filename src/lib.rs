@@ -2,6 +2,28 @@ pub mod engine;
 mod game;
 
 pub fn run() {
-    let game = game::lowrez_game::LowRezGame::new(60);
-    engine::start_game(Box::new(game));
+    run_with_options(None, engine::replay::ReplayOptions::default());
+}
+
+// Run with a fixed world seed, eg. to reproduce a bug report.
+pub fn run_with_seed(world_seed: u64) {
+    run_with_options(Some(world_seed), engine::replay::ReplayOptions::default());
+}
+
+// Replays store the seed they were recorded with, which is used instead of a random one.
+pub fn run_with_options(
+    world_seed: Option<u64>,
+    mut replay_options: engine::replay::ReplayOptions,
+) {
+    let world_seed = match replay_options.resolve_seed(world_seed) {
+        Ok(seed) => seed.unwrap_or_else(rand::random),
+        Err(e) => {
+            eprintln!("Failed to start the replay: {}", e);
+            return;
+        }
+    };
+    replay_options.seed = Some(world_seed);
+
+    let game = game::lowrez_game::LowRezGame::with_seed(60, world_seed);
+    engine::start_game_with_replay(Box::new(game), replay_options);
 }
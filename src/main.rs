@@ -1,4 +1,19 @@
 #![windows_subsystem = "windows"]
+use wgpu_lowrez_engine::engine::replay::ReplayOptions;
+
+// Usage: [world seed] [--record <file>] [--replay <file>]
 fn main() {
-    wgpu_lowrez_engine::run();
+    let mut world_seed = None;
+    let mut replay_options = ReplayOptions::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => replay_options.record_path = args.next().map(Into::into),
+            "--replay" => replay_options.playback_path = args.next().map(Into::into),
+            _ => world_seed = arg.parse().ok(),
+        }
+    }
+
+    wgpu_lowrez_engine::run_with_options(world_seed, replay_options);
 }